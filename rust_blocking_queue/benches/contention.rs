@@ -0,0 +1,75 @@
+/////////////////////////////////////////////////////////////
+// rust_blocking_queue::contention.rs - backend benchmark  //
+//                                                         //
+// Jim Fawcett, https://JimFawcett.github.io, 18 Oct 2026  //
+/////////////////////////////////////////////////////////////
+/*
+   Compares BlockingQueue backends, Mutex and lock-free, with
+   1, 4, and 16 producers and as many consumers, all moving
+   MESSAGES elements through one queue, unbounded and with
+   a small capacity.
+
+   cargo bench --bench contention
+*/
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rust_blocking_queue::BlockingQueue;
+use std::sync::Arc;
+use std::thread;
+
+const MESSAGES: usize = 64 * 1024;
+
+fn make_queue(lock_free: bool, capacity: Option<usize>) -> BlockingQueue<usize> {
+    let q = match capacity {
+        Some(cap) => BlockingQueue::with_capacity(cap),
+        None => BlockingQueue::new(),
+    };
+    if lock_free { q.lock_free() } else { q }
+}
+
+/*-- n producers and n consumers share MESSAGES elements --*/
+fn run(q: Arc<BlockingQueue<usize>>, n: usize) {
+    let per_producer = MESSAGES / n;
+    let consumers: Vec<_> = (0..n).map(|_| {
+        let q = Arc::clone(&q);
+        thread::spawn(move || while q.de_q().is_some() {})
+    }).collect();
+    let producers: Vec<_> = (0..n).map(|_| {
+        let q = Arc::clone(&q);
+        thread::spawn(move || {
+            for i in 0..per_producer {
+                q.en_q(i).unwrap();
+            }
+        })
+    }).collect();
+    for producer in producers {
+        producer.join().unwrap();
+    }
+    q.close();
+    for consumer in consumers {
+        consumer.join().unwrap();
+    }
+}
+
+fn contention(c: &mut Criterion) {
+    for capacity in [None, Some(256)] {
+        let name = match capacity {
+            Some(_) => "bounded",
+            None => "unbounded",
+        };
+        let mut group = c.benchmark_group(name);
+        group.throughput(Throughput::Elements(MESSAGES as u64));
+        group.sample_size(20);
+        for n in [1, 4, 16] {
+            for (backend, lock_free) in [("mutex", false), ("lock_free", true)] {
+                group.bench_with_input(BenchmarkId::new(backend, n), &n, |b, &n| {
+                    b.iter(|| run(Arc::new(make_queue(lock_free, capacity)), n));
+                });
+            }
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, contention);
+criterion_main!(benches);
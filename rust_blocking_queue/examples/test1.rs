@@ -34,13 +34,10 @@ fn test() {
     let handle = thread::spawn(move || {
         print!("\n  child thread started");
         flush();
-        loop {
-            let t = share1.de_q();
+        /*-- de_q returns None once queue is closed and drained --*/
+        while let Some(t) = share1.de_q() {
             print!("\n  dequeued {} on child thread", t);
             flush();
-            if &t == "quit" {
-                break;
-            }
         }
         print!("\n  thread shutting down");
         flush();
@@ -48,15 +45,15 @@ fn test() {
 
     /*-- main thread enqueues messages --*/
     for i in 0..5 {
-        let msg = format!("msg #{}", i.to_string());
+        let msg = format!("msg #{}", i);
         print!("\n  enqueued {:?} on main thread", msg);
         flush();
-        share2.en_q(msg);
+        let _ = share2.en_q(msg);
     }
     /*-- shut down child thread --*/
-    print!("\n  enqueued {:?} on main thread", "quit");
+    print!("\n  closing queue on main thread");
     flush();
-    share2.en_q("quit".to_string());
+    share2.close();
 
     /*-- child thread must complete before exiting --*/
     print!("\n  waiting for child thread to stop");
@@ -0,0 +1,288 @@
+/////////////////////////////////////////////////////////////
+// rust_blocking_queue::delay_queue.rs - DelayQueue        //
+//                                                         //
+// Jim Fawcett, https://JimFawcett.github.io, 18 Oct 2026  //
+/////////////////////////////////////////////////////////////
+/*
+   DelayQueue<T> blocks like BlockingQueue<T>, but each element
+   stays hidden until its own Instant, given to en_q_at or
+   en_q_after.  en_q makes elements visible at once.
+
+   Elements leave in order of visibility, those visible at the
+   same Instant in the order queued.  Closing doesn't discard
+   delayed elements, de_q waits for them before returning None.
+*/
+
+use crate::Queue;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::*;
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+struct Entry<T> {
+    at: Instant,
+    seq: u64,
+    item: T,
+}
+impl<T> PartialEq for Entry<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl<T> Eq for Entry<T> {}
+impl<T> PartialOrd for Entry<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+/*-- max-heap: earlier Instant first, then earlier seq --*/
+impl<T> Ord for Entry<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.at.cmp(&self.at)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+#[derive(Debug)]
+struct State<T> {
+    heap: BinaryHeap<Entry<T>>,
+    next_seq: u64,
+    closed: bool,
+}
+
+#[derive(Debug)]
+/// Thread-safe queue that blocks like BlockingQueue,
+/// elements become visible to de_q at their own Instant
+pub struct DelayQueue<T> {
+    q: Mutex<State<T>>,
+    cv: Condvar,
+    not_full: Condvar,
+    capacity: Option<usize>,
+}
+impl<T> std::default::Default for DelayQueue<T> {
+    fn default() -> DelayQueue<T> {
+        DelayQueue::new()
+    }
+}
+impl<T> DelayQueue<T> {
+    /// Create empty, unbounded delay queue
+    pub fn new() -> Self {
+        Self {
+            q: Mutex::new(State { heap: BinaryHeap::new(), next_seq: 0, closed: false }),
+            cv: Condvar::new(),
+            not_full: Condvar::new(),
+            capacity: None,
+        }
+    }
+    /// Create empty queue holding at most cap elements, hidden or not
+    /// - panics if cap is zero
+    pub fn with_capacity(cap: usize) -> Self {
+        assert!(cap > 0, "DelayQueue capacity must be at least 1");
+        Self {
+            q: Mutex::new(State {
+                heap: BinaryHeap::with_capacity(cap), next_seq: 0, closed: false
+            }),
+            cv: Condvar::new(),
+            not_full: Condvar::new(),
+            capacity: Some(cap),
+        }
+    }
+    /// push input, visible now, waiting for room if full
+    /// - returns input to caller if queue is closed
+    pub fn en_q(&self, t:T) -> Result<(), T> {
+        self.en_q_at(t, Instant::now())
+    }
+    /// push input, visible after delay, waiting for room if full
+    /// - a delay past the latest representable Instant is
+    ///   clamped to it, so Err always means closed
+    pub fn en_q_after(&self, t:T, delay: Duration) -> Result<(), T> {
+        self.en_q_at(t, saturating_after(Instant::now(), delay))
+    }
+    /// push input, visible at Instant at, waiting for room if full
+    pub fn en_q_at(&self, t:T, at: Instant) -> Result<(), T> {
+        let mut lq = self.q.lock().unwrap();
+        while !lq.closed && self.is_full(&lq) {
+            lq = self.not_full.wait(lq).unwrap();
+        }
+        if lq.closed {
+            return Err(t);
+        }
+        self.push(&mut lq, t, at);
+        Ok(())
+    }
+    /// push input, visible now, if there's room and the queue
+    /// is open, else return it to the caller
+    pub fn try_en_q(&self, t:T) -> Result<(), T> {
+        let mut lq = self.q.lock().unwrap();
+        if lq.closed || self.is_full(&lq) {
+            return Err(t);
+        }
+        self.push(&mut lq, t, Instant::now());
+        Ok(())
+    }
+    /// pop first visible element, waiting until there is one
+    /// - None once queue is closed and drained
+    pub fn de_q(&self) -> Option<T> {
+        let mut lq = self.q.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            if let Some(t) = self.pop_visible(&mut lq, now) {
+                return Some(t);
+            }
+            lq = match lq.heap.peek().map(|entry| entry.at) {
+                Some(at) => self.cv.wait_timeout(lq, at - now).unwrap().0,
+                None if lq.closed => return None,
+                None => self.cv.wait(lq).unwrap(),
+            };
+        }
+    }
+    /// pop first visible element if there is one
+    pub fn try_de_q(&self) -> Option<T> {
+        let mut lq = self.q.lock().unwrap();
+        self.pop_visible(&mut lq, Instant::now())
+    }
+    /// pop element, waiting at most timeout for one to be visible
+    pub fn de_q_timeout(&self, timeout: Duration) -> Option<T> {
+        match Instant::now().checked_add(timeout) {
+            Some(deadline) => self.de_q_deadline(deadline),
+            None => self.de_q(),
+        }
+    }
+    /// pop element, waiting until deadline for one to be visible
+    pub fn de_q_deadline(&self, deadline: Instant) -> Option<T> {
+        let mut lq = self.q.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            if let Some(t) = self.pop_visible(&mut lq, now) {
+                return Some(t);
+            }
+            if now >= deadline || (lq.closed && lq.heap.is_empty()) {
+                return None;
+            }
+            let until = lq.heap.peek().map_or(deadline, |entry| entry.at.min(deadline));
+            lq = self.cv.wait_timeout(lq, until - now).unwrap().0;
+        }
+    }
+    /// Instant the next element becomes visible, None if empty
+    pub fn next_visible(&self) -> Option<Instant> {
+        self.q.lock().unwrap().heap.peek().map(|entry| entry.at)
+    }
+    /// no more elements will be accepted, wakes all waiters
+    /// - delayed elements are still delivered when visible
+    pub fn close(&self) {
+        self.q.lock().unwrap().closed = true;
+        self.cv.notify_all();
+        self.not_full.notify_all();
+    }
+    pub fn is_closed(&self) -> bool {
+        self.q.lock().unwrap().closed
+    }
+    /// number of elements queued, visible or not
+    pub fn len(&self) -> usize {
+        self.q.lock().unwrap().heap.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.q.lock().unwrap().heap.is_empty()
+    }
+    pub fn capacity(&self) -> Option<usize> {
+        self.capacity
+    }
+    /*-- new element may be visible sooner than waiters expect --*/
+    fn push(&self, lq: &mut State<T>, item: T, at: Instant) {
+        let seq = lq.next_seq;
+        lq.next_seq += 1;
+        lq.heap.push(Entry { at, seq, item });
+        self.cv.notify_one();
+    }
+    fn pop_visible(&self, lq: &mut State<T>, now: Instant) -> Option<T> {
+        if lq.heap.peek()?.at > now {
+            return None;
+        }
+        let entry = lq.heap.pop()?;
+        self.not_full.notify_one();
+        Some(entry.item)
+    }
+    fn is_full(&self, lq: &State<T>) -> bool {
+        self.capacity.is_some_and(|cap| lq.heap.len() >= cap)
+    }
+}
+/*-- now + delay, or as near as Instant can get to it --*/
+fn saturating_after(now: Instant, delay: Duration) -> Instant {
+    if let Some(at) = now.checked_add(delay) {
+        return at;
+    }
+    let mut at = now;
+    let mut step = delay / 2;
+    while step > Duration::ZERO {
+        match at.checked_add(step) {
+            Some(later) => at = later,
+            None => step /= 2,
+        }
+    }
+    at
+}
+impl<T> Queue<T> for DelayQueue<T> {
+    fn en_q(&self, t: T) -> Result<(), T> { DelayQueue::en_q(self, t) }
+    fn try_en_q(&self, t: T) -> Result<(), T> { DelayQueue::try_en_q(self, t) }
+    fn de_q(&self) -> Option<T> { DelayQueue::de_q(self) }
+    fn try_de_q(&self) -> Option<T> { DelayQueue::try_de_q(self) }
+    fn de_q_deadline(&self, deadline: Instant) -> Option<T> {
+        DelayQueue::de_q_deadline(self, deadline)
+    }
+    fn close(&self) { DelayQueue::close(self) }
+    fn is_closed(&self) -> bool { DelayQueue::is_closed(self) }
+    fn len(&self) -> usize { DelayQueue::len(self) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn dq_hides_elements_until_their_instant() {
+        let dq = DelayQueue::<&str>::new();
+        let start = Instant::now();
+        dq.en_q_after("late", Duration::from_millis(60)).unwrap();
+        dq.en_q_after("soon", Duration::from_millis(30)).unwrap();
+        dq.en_q("now").unwrap();
+        assert_eq!(dq.try_de_q(), Some("now"));
+        assert_eq!(dq.try_de_q(), None);
+        assert_eq!(dq.de_q_timeout(Duration::from_millis(5)), None);
+        assert_eq!(dq.de_q(), Some("soon"));
+        assert!(start.elapsed() >= Duration::from_millis(30));
+        assert_eq!(dq.de_q(), Some("late"));
+        assert!(start.elapsed() >= Duration::from_millis(60));
+        assert!(dq.is_empty());
+    }
+    #[test]
+    fn dq_earlier_element_wakes_waiting_consumer() {
+        let dq = Arc::new(DelayQueue::<i32>::new());
+        dq.en_q_after(1, Duration::from_secs(30)).unwrap();
+        let consumer = {
+            let dq = Arc::clone(&dq);
+            std::thread::spawn(move || dq.de_q())
+        };
+        std::thread::sleep(Duration::from_millis(20));
+        let start = Instant::now();
+        dq.en_q_after(2, Duration::from_millis(20)).unwrap();
+        assert_eq!(consumer.join().unwrap(), Some(2));
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+    #[test]
+    fn dq_close_delivers_delayed_then_ends() {
+        let dq = DelayQueue::<i32>::new();
+        dq.en_q_after(1, Duration::from_millis(20)).unwrap();
+        dq.close();
+        assert_eq!(dq.en_q(2), Err(2));
+        assert_eq!(dq.de_q(), Some(1));
+        assert_eq!(dq.de_q(), None);
+    }
+    #[test]
+    fn dq_huge_delay_saturates_instead_of_failing() {
+        let dq = DelayQueue::<i32>::new();
+        assert_eq!(dq.en_q_after(1, Duration::MAX), Ok(()));
+        assert_eq!(dq.len(), 1);
+        assert_eq!(dq.try_de_q(), None);
+        assert!(dq.next_visible().unwrap() > Instant::now());
+    }
+}
@@ -14,55 +14,478 @@
    
    There is another alternative, based on Rust channels, which 
    are essentially blocking queues.
+
+   A queue made with_capacity(n) holds at most n items, en_q
+   blocks while it's full, so producers can't outrun their
+   consumers.  try_en_q hands the item back instead.
+
+   try_de_q, de_q_timeout, and de_q_deadline give up instead
+   of waiting forever, so a consumer can check for shutdown
+   while the queue is idle.
+
+   close() ends the queue without sentinel messages: waiters
+   wake, de_q returns None once the queue is drained, and
+   en_q hands items back.
+
+   de_q_batch, en_q_all, drain, and clear move many elements
+   per lock, for consumers that work in bulk.
+
+   PriorityQueue and DelayQueue, in their own modules, change
+   the order elements leave.  All three implement Queue, the
+   blocking, timeout, and close operations they share, so
+   code written against Queue can use any of them.
+
+   Select, in select.rs, waits on several BlockingQueues.
+
+   A queue made with with_stats() counts its traffic, wait
+   times, and queued times, see stats.rs, read with stats().
+
+   lock_free() swaps the Mutex<VecDeque> for a lock-free
+   backend, see lock_free.rs, for queues shared by many
+   busy threads.  Every operation behaves the same.
 */
 #![allow(dead_code)]
 use std::sync::*;
+use std::sync::atomic::{self, AtomicUsize, Ordering};
 use std::collections::*;
+use std::time::{Duration, Instant};
+
+mod priority_queue;
+pub use priority_queue::*;
+mod delay_queue;
+pub use delay_queue::*;
+mod select;
+pub use select::Select;
+use select::Signal;
+mod stats;
+pub use stats::QueueStats;
+use stats::Stats;
+mod lock_free;
+use lock_free::{LockFree, Slot};
+
+/// Operations shared by BlockingQueue, PriorityQueue, and DelayQueue
+pub trait Queue<T> {
+    /// push, waiting for room if full, returns t if closed
+    fn en_q(&self, t: T) -> Result<(), T>;
+    /// push if there's room and the queue is open, else return t
+    fn try_en_q(&self, t: T) -> Result<(), T>;
+    /// pop, waiting if empty, None once closed and drained
+    fn de_q(&self) -> Option<T>;
+    /// pop without waiting
+    fn try_de_q(&self) -> Option<T>;
+    /// pop, waiting until deadline at most
+    fn de_q_deadline(&self, deadline: Instant) -> Option<T>;
+    /// pop, waiting at most timeout
+    fn de_q_timeout(&self, timeout: Duration) -> Option<T> {
+        match Instant::now().checked_add(timeout) {
+            Some(deadline) => self.de_q_deadline(deadline),
+            None => self.de_q(),
+        }
+    }
+    /// refuse further elements and wake all waiters
+    fn close(&self);
+    fn is_closed(&self) -> bool;
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[derive(Debug)]
+struct State<T> {
+    items: VecDeque<T>,
+    /* en_q times, parallel to items, kept only with stats */
+    stamps: VecDeque<Instant>,
+    closed: bool,
+    watchers: Vec<Arc<Signal>>,
+}
+impl<T> State<T> {
+    fn new(items: VecDeque<T>) -> State<T> {
+        State { items, stamps: VecDeque::new(), closed: false, watchers: Vec::new() }
+    }
+    /*-- tell Selects waiting on this queue to look again --*/
+    fn fire_watchers(&self) {
+        for signal in &self.watchers {
+            signal.fire();
+        }
+    }
+}
 
 #[derive(Debug)]
-/// Thread-safe queue that blocks de_q on empty
+/// Thread-safe queue that blocks de_q on empty,
+/// and en_q on full if it has a capacity
 pub struct BlockingQueue<T> {
-    q: Mutex<VecDeque<T>>,
+    q: Mutex<State<T>>,
     cv: Condvar,
+    not_full: Condvar,
+    capacity: Option<usize>,
+    stats: Option<Stats>,
+    lock_free: Option<LockFree<T>>,
+    /* Selects watching, lets lock-free en_q skip locking q */
+    watching: AtomicUsize,
 }
 impl<T> std::default::Default for BlockingQueue<T> {
     fn default() -> BlockingQueue<T> {
-        BlockingQueue {
-            q: Mutex::new(VecDeque::new()),
-            cv: Condvar::new(),
-        }
+        BlockingQueue::new()
     }
 }
 impl<T> BlockingQueue<T> {
-    /// Create empty blocking queue
+    /// Create empty, unbounded blocking queue
     pub fn new() -> Self {
         Self {
-            q: Mutex::new(VecDeque::new()),
+            q: Mutex::new(State::new(VecDeque::new())),
+            cv: Condvar::new(),
+            not_full: Condvar::new(),
+            capacity: None,
+            stats: None,
+            lock_free: None,
+            watching: AtomicUsize::new(0),
+        }
+    }
+    /// Create empty queue holding at most cap elements
+    /// - panics if cap is zero, nothing could ever be queued
+    pub fn with_capacity(cap: usize) -> Self {
+        assert!(cap > 0, "BlockingQueue capacity must be at least 1");
+        Self {
+            q: Mutex::new(State::new(VecDeque::with_capacity(cap))),
             cv: Condvar::new(),
+            not_full: Condvar::new(),
+            capacity: Some(cap),
+            stats: None,
+            lock_free: None,
+            watching: AtomicUsize::new(0),
         }
     }
-    /// push input on back of queue
+    /// Keep counters for stats(), e.g., BlockingQueue::new().with_stats()
+    pub fn with_stats(mut self) -> Self {
+        self.stats = Some(Stats::default());
+        self
+    }
+    /// Use the lock-free backend, e.g., BlockingQueue::with_capacity(64).lock_free()
+    /// - panics if elements were already queued
+    pub fn lock_free(mut self) -> Self {
+        assert!(
+            self.q.get_mut().unwrap().items.is_empty(),
+            "BlockingQueue must be empty to change backend"
+        );
+        self.lock_free = Some(LockFree::new(self.capacity));
+        self
+    }
+    /// push input on back of queue, waiting for room if full
+    /// - returns input to caller if queue is closed
     /// - unrecoverable if lock fails so just unwrap
-    pub fn en_q(&self, t:T) {
+    pub fn en_q(&self, t:T) -> Result<(), T> {
+        if let Some(lf) = &self.lock_free {
+            return self.lf_en_q(lf, t, true);
+        }
         let mut lq = self.q.lock().unwrap();
-        lq.push_back(t);
+        while !lq.closed && self.is_full(&lq) {
+            lq = self.not_full.wait(lq).unwrap();
+        }
+        if lq.closed {
+            return Err(t);
+        }
+        self.push(&mut lq, t);
+        lq.fire_watchers();
         self.cv.notify_one();
+        Ok(())
     }
-    /// pop element from front of queue
+    /// push input on back of queue if there's room and the
+    /// queue is open, else return it to the caller
+    pub fn try_en_q(&self, t:T) -> Result<(), T> {
+        if let Some(lf) = &self.lock_free {
+            return self.lf_en_q(lf, t, false);
+        }
+        let mut lq = self.q.lock().unwrap();
+        if lq.closed || self.is_full(&lq) {
+            return Err(t);
+        }
+        self.push(&mut lq, t);
+        lq.fire_watchers();
+        self.cv.notify_one();
+        Ok(())
+    }
+    /// pop element from front of queue, waiting if empty
+    /// - None once queue is closed and drained
     /// - unrecoverable if lock fails so just unwrap
     /// - same for condition variable
-    pub fn de_q(&self) -> T {
+    pub fn de_q(&self) -> Option<T> {
+        if let Some(lf) = &self.lock_free {
+            return self.lf_de_q(lf, None);
+        }
+        let start = self.wait_start();
+        let mut lq = self.q.lock().unwrap();
+        loop {
+            if let Some(t) = self.pop(&mut lq) {
+                self.not_full.notify_one();
+                self.wait_end(start);
+                return Some(t);
+            }
+            if lq.closed {
+                self.wait_end(start);
+                return None;
+            }
+            lq = self.cv.wait(lq).unwrap();
+        }
+    }
+    /// push all items, in order, waiting for room as needed
+    /// - consumers are woken before waiting, so they can make room
+    /// - returns items not queued if queue is closed
+    pub fn en_q_all<I>(&self, items: I) -> Result<(), Vec<T>>
+    where I: IntoIterator<Item = T> {
+        let mut pending: VecDeque<T> = items.into_iter().collect();
+        if let Some(lf) = &self.lock_free {
+            while let Some(t) = pending.pop_front() {
+                if let Err(t) = self.lf_en_q(lf, t, true) {
+                    pending.push_front(t);
+                    return Err(pending.into());
+                }
+            }
+            return Ok(());
+        }
+        let mut lq = self.q.lock().unwrap();
+        while !pending.is_empty() {
+            while !lq.closed && self.is_full(&lq) {
+                lq.fire_watchers();
+                self.cv.notify_all();
+                lq = self.not_full.wait(lq).unwrap();
+            }
+            if lq.closed {
+                lq.fire_watchers();
+                self.cv.notify_all();
+                return Err(pending.into());
+            }
+            let room = match self.capacity {
+                Some(cap) => cap - lq.items.len(),
+                None => pending.len(),
+            };
+            for t in pending.drain(..room.min(pending.len())) {
+                self.push(&mut lq, t);
+            }
+        }
+        lq.fire_watchers();
+        self.cv.notify_all();
+        Ok(())
+    }
+    /// pop up to max elements, waiting if empty
+    /// - empty once queue is closed and drained, or max is zero
+    /// - wakes another consumer if elements are left over
+    pub fn de_q_batch(&self, max: usize) -> Vec<T> {
+        if max == 0 {
+            return Vec::new();
+        }
+        if let Some(lf) = &self.lock_free {
+            let first = self.lf_de_q(lf, None);
+            let rest = std::iter::from_fn(|| self.try_de_q()).take(max - 1);
+            return first.into_iter().chain(rest).collect();
+        }
+        let start = self.wait_start();
         let mut lq = self.q.lock().unwrap();
-        while lq.len() == 0 {
+        while lq.items.is_empty() && !lq.closed {
             lq = self.cv.wait(lq).unwrap();
         }
-        lq.pop_front().unwrap()
+        self.wait_end(start);
+        let n = max.min(lq.items.len());
+        let batch: Vec<T> = (0..n).filter_map(|_| self.pop(&mut lq)).collect();
+        if !lq.items.is_empty() {
+            self.cv.notify_one();
+        }
+        if !batch.is_empty() {
+            self.not_full.notify_all();
+        }
+        batch
+    }
+    /// remove and return all queued elements without waiting
+    pub fn drain(&self) -> Vec<T> {
+        if self.lock_free.is_some() {
+            return std::iter::from_fn(|| self.try_de_q()).collect();
+        }
+        let mut lq = self.q.lock().unwrap();
+        let all: Vec<T> = std::iter::from_fn(|| self.pop(&mut lq)).collect();
+        self.not_full.notify_all();
+        all
+    }
+    /// discard all queued elements, stats don't count them as dequeued
+    pub fn clear(&self) {
+        if let Some(lf) = &self.lock_free {
+            while lf.try_de_q().is_some() {}
+            return;
+        }
+        let mut lq = self.q.lock().unwrap();
+        lq.items.clear();
+        lq.stamps.clear();
+        self.not_full.notify_all();
+    }
+    /// pop element from front of queue if there is one
+    pub fn try_de_q(&self) -> Option<T> {
+        if let Some(lf) = &self.lock_free {
+            return lf.try_de_q().map(|slot| self.lf_took(slot));
+        }
+        let mut lq = self.q.lock().unwrap();
+        let t = self.pop(&mut lq)?;
+        self.not_full.notify_one();
+        Some(t)
+    }
+    /// pop element, waiting at most timeout for one to arrive
+    pub fn de_q_timeout(&self, timeout: Duration) -> Option<T> {
+        match Instant::now().checked_add(timeout) {
+            Some(deadline) => self.de_q_deadline(deadline),
+            None => self.de_q(),
+        }
+    }
+    /// pop element, waiting until deadline for one to arrive
+    /// - wakeups without an element just wait out the rest
+    /// - None at once if queue is closed and drained
+    pub fn de_q_deadline(&self, deadline: Instant) -> Option<T> {
+        if let Some(lf) = &self.lock_free {
+            return self.lf_de_q(lf, Some(deadline));
+        }
+        let start = self.wait_start();
+        let mut lq = self.q.lock().unwrap();
+        loop {
+            if let Some(t) = self.pop(&mut lq) {
+                self.not_full.notify_one();
+                self.wait_end(start);
+                return Some(t);
+            }
+            let now = Instant::now();
+            if lq.closed || now >= deadline {
+                self.wait_end(start);
+                return None;
+            }
+            lq = self.cv.wait_timeout(lq, deadline - now).unwrap().0;
+        }
+    }
+    /// no more elements will be accepted, wakes all waiters
+    /// - elements already queued can still be dequeued
+    pub fn close(&self) {
+        if let Some(lf) = &self.lock_free {
+            lf.close();
+            self.lf_fire_watchers();
+            return;
+        }
+        let mut lq = self.q.lock().unwrap();
+        lq.closed = true;
+        lq.fire_watchers();
+        self.cv.notify_all();
+        self.not_full.notify_all();
+    }
+    pub fn is_closed(&self) -> bool {
+        match &self.lock_free {
+            Some(lf) => lf.is_closed(),
+            None => self.q.lock().unwrap().closed,
+        }
     }
     /// return number of elements in queue
     pub fn len(&self) -> usize {
-        self.q.lock().unwrap().len()
+        match &self.lock_free {
+            Some(lf) => lf.len(),
+            None => self.q.lock().unwrap().items.len(),
+        }
+    }
+    /// true if queue holds no elements
+    pub fn is_empty(&self) -> bool {
+        match &self.lock_free {
+            Some(lf) => lf.is_empty(),
+            None => self.q.lock().unwrap().items.is_empty(),
+        }
+    }
+    /// most elements queue holds, None if unbounded
+    pub fn capacity(&self) -> Option<usize> {
+        self.capacity
+    }
+    /// counters so far, None unless made with_stats()
+    pub fn stats(&self) -> Option<QueueStats> {
+        self.stats.as_ref().map(Stats::snapshot)
+    }
+    fn is_full(&self, lq: &State<T>) -> bool {
+        self.capacity.is_some_and(|cap| lq.items.len() >= cap)
+    }
+    fn push(&self, lq: &mut State<T>, t: T) {
+        lq.items.push_back(t);
+        if let Some(stats) = &self.stats {
+            lq.stamps.push_back(Instant::now());
+            stats.record_en_q(lq.items.len());
+        }
+    }
+    fn pop(&self, lq: &mut State<T>) -> Option<T> {
+        let t = lq.items.pop_front()?;
+        if let (Some(stats), Some(at)) = (&self.stats, lq.stamps.pop_front()) {
+            stats.record_de_q(at.elapsed());
+        }
+        Some(t)
+    }
+    fn wait_start(&self) -> Option<Instant> {
+        self.stats.as_ref().map(|_| Instant::now())
+    }
+    fn wait_end(&self, start: Option<Instant>) {
+        if let (Some(stats), Some(start)) = (&self.stats, start) {
+            stats.record_wait(start.elapsed());
+        }
+    }
+    /*-- closed, and nothing left or on its way --*/
+    fn is_drained(&self) -> bool {
+        match &self.lock_free {
+            Some(lf) => lf.is_drained(),
+            None => {
+                let lq = self.q.lock().unwrap();
+                lq.closed && lq.items.is_empty()
+            }
+        }
+    }
+    fn watch(&self, signal: &Arc<Signal>) {
+        self.q.lock().unwrap().watchers.push(Arc::clone(signal));
+        self.watching.fetch_add(1, Ordering::SeqCst);
+        atomic::fence(Ordering::SeqCst);
+    }
+    fn unwatch(&self, signal: &Arc<Signal>) {
+        self.q.lock().unwrap().watchers.retain(|w| !Arc::ptr_eq(w, signal));
+        self.watching.fetch_sub(1, Ordering::SeqCst);
+    }
+    /*-----------------------------------------------------
+      Lock-free backend, stats and Select watchers handled
+      here, as the Mutex backend does under its lock
+    */
+    fn lf_en_q(&self, lf: &LockFree<T>, t: T, block: bool) -> Result<(), T> {
+        let stamp = self.stats.as_ref().map(|_| Instant::now());
+        lf.en_q((t, stamp), block).map_err(|(t, _)| t)?;
+        if let Some(stats) = &self.stats {
+            stats.record_en_q(lf.len());
+        }
+        self.lf_fire_watchers();
+        Ok(())
+    }
+    fn lf_de_q(&self, lf: &LockFree<T>, deadline: Option<Instant>) -> Option<T> {
+        let start = self.wait_start();
+        let slot = lf.de_q(deadline);
+        self.wait_end(start);
+        slot.map(|slot| self.lf_took(slot))
+    }
+    fn lf_took(&self, (t, at): Slot<T>) -> T {
+        if let (Some(stats), Some(at)) = (&self.stats, at) {
+            stats.record_de_q(at.elapsed());
+        }
+        t
+    }
+    fn lf_fire_watchers(&self) {
+        atomic::fence(Ordering::SeqCst);
+        if self.watching.load(Ordering::SeqCst) > 0 {
+            self.q.lock().unwrap().fire_watchers();
+        }
     }
 }
+impl<T> Queue<T> for BlockingQueue<T> {
+    fn en_q(&self, t: T) -> Result<(), T> { BlockingQueue::en_q(self, t) }
+    fn try_en_q(&self, t: T) -> Result<(), T> { BlockingQueue::try_en_q(self, t) }
+    fn de_q(&self) -> Option<T> { BlockingQueue::de_q(self) }
+    fn try_de_q(&self) -> Option<T> { BlockingQueue::try_de_q(self) }
+    fn de_q_deadline(&self, deadline: Instant) -> Option<T> {
+        BlockingQueue::de_q_deadline(self, deadline)
+    }
+    fn close(&self) { BlockingQueue::close(self) }
+    fn is_closed(&self) -> bool { BlockingQueue::is_closed(self) }
+    fn len(&self) -> usize { BlockingQueue::len(self) }
+}
 
 #[cfg(test)]
 mod tests {
@@ -75,14 +498,224 @@ mod tests {
     #[test]
     fn bq_en_queue() {
         let bq = BlockingQueue::<f64>::new();
-        bq.en_q(3.5);
+        bq.en_q(3.5).unwrap();
         assert_eq!(bq.len(), 1);
     }
     #[test]
     fn bq_de_queue() {
         let bq = BlockingQueue::<f64>::new();
-        bq.en_q(3.5);
-        assert_eq!(bq.de_q(), 3.5);
+        bq.en_q(3.5).unwrap();
+        assert_eq!(bq.de_q(), Some(3.5));
         assert_eq!(bq.len(), 0);
     }
+    #[test]
+    fn bq_try_en_q_returns_item_when_full() {
+        let bq = BlockingQueue::<i32>::with_capacity(2);
+        assert_eq!(bq.capacity(), Some(2));
+        assert_eq!(bq.try_en_q(1), Ok(()));
+        assert_eq!(bq.try_en_q(2), Ok(()));
+        assert_eq!(bq.try_en_q(3), Err(3));
+        assert_eq!(bq.de_q(), Some(1));
+        assert_eq!(bq.try_en_q(3), Ok(()));
+        assert_eq!(bq.len(), 2);
+    }
+    #[test]
+    fn bq_en_q_blocks_until_room() {
+        let bq = Arc::new(BlockingQueue::<i32>::with_capacity(1));
+        bq.en_q(1).unwrap();
+        let producer = {
+            let bq = Arc::clone(&bq);
+            std::thread::spawn(move || bq.en_q(2).unwrap())
+        };
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert_eq!(bq.len(), 1);
+        assert_eq!(bq.de_q(), Some(1));
+        producer.join().unwrap();
+        assert_eq!(bq.de_q(), Some(2));
+        assert!(bq.is_empty());
+    }
+    #[test]
+    fn bq_try_de_q() {
+        let bq = BlockingQueue::<i32>::with_capacity(1);
+        assert_eq!(bq.try_de_q(), None);
+        bq.en_q(7).unwrap();
+        assert_eq!(bq.try_de_q(), Some(7));
+        assert_eq!(bq.try_en_q(8), Ok(()));
+    }
+    #[test]
+    fn bq_de_q_timeout_waits_about_timeout() {
+        let bq = BlockingQueue::<i32>::new();
+        let timeout = Duration::from_millis(50);
+        let start = Instant::now();
+        assert_eq!(bq.de_q_timeout(timeout), None);
+        let elapsed = start.elapsed();
+        assert!(elapsed >= timeout);
+        assert!(elapsed < timeout + Duration::from_millis(500));
+        assert_eq!(bq.de_q_deadline(Instant::now()), None);
+    }
+    #[test]
+    fn bq_de_q_timeout_returns_early_with_element() {
+        let bq = Arc::new(BlockingQueue::<i32>::new());
+        let producer = {
+            let bq = Arc::clone(&bq);
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(20));
+                bq.en_q(5).unwrap();
+            })
+        };
+        let start = Instant::now();
+        assert_eq!(bq.de_q_timeout(Duration::from_secs(10)), Some(5));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        producer.join().unwrap();
+    }
+    #[test]
+    fn bq_de_q_deadline_ignores_spurious_wakeups() {
+        let bq = Arc::new(BlockingQueue::<i32>::new());
+        let run = Arc::new(atomic::AtomicBool::new(true));
+        let waker = {
+            let (bq, run) = (Arc::clone(&bq), Arc::clone(&run));
+            std::thread::spawn(move || {
+                while run.load(atomic::Ordering::Relaxed) {
+                    bq.cv.notify_all();
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+            })
+        };
+        let deadline = Instant::now() + Duration::from_millis(60);
+        assert_eq!(bq.de_q_deadline(deadline), None);
+        assert!(Instant::now() >= deadline);
+        run.store(false, atomic::Ordering::Relaxed);
+        waker.join().unwrap();
+    }
+    #[test]
+    fn bq_close_drains_then_ends() {
+        let bq = BlockingQueue::<i32>::new();
+        bq.en_q(1).unwrap();
+        bq.close();
+        assert!(bq.is_closed());
+        assert_eq!(bq.en_q(2), Err(2));
+        assert_eq!(bq.try_en_q(3), Err(3));
+        assert_eq!(bq.de_q(), Some(1));
+        assert_eq!(bq.de_q(), None);
+        assert_eq!(bq.de_q_timeout(Duration::from_secs(10)), None);
+    }
+    #[test]
+    fn bq_close_wakes_blocked_consumers_and_producers() {
+        let empty = Arc::new(BlockingQueue::<i32>::new());
+        let full = Arc::new(BlockingQueue::<i32>::with_capacity(1));
+        full.en_q(1).unwrap();
+        let consumers: Vec<_> = (0..3).map(|_| {
+            let q = Arc::clone(&empty);
+            std::thread::spawn(move || q.de_q())
+        }).collect();
+        let producer = {
+            let q = Arc::clone(&full);
+            std::thread::spawn(move || q.en_q(2))
+        };
+        std::thread::sleep(Duration::from_millis(20));
+        empty.close();
+        full.close();
+        for consumer in consumers {
+            assert_eq!(consumer.join().unwrap(), None);
+        }
+        assert_eq!(producer.join().unwrap(), Err(2));
+        assert_eq!(full.de_q(), Some(1));
+    }
+    #[test]
+    fn bq_de_q_batch_takes_up_to_max() {
+        let bq = BlockingQueue::<i32>::new();
+        bq.en_q_all(1..=5).unwrap();
+        assert_eq!(bq.de_q_batch(3), vec![1, 2, 3]);
+        assert_eq!(bq.de_q_batch(0), Vec::<i32>::new());
+        assert_eq!(bq.de_q_batch(10), vec![4, 5]);
+        bq.close();
+        assert_eq!(bq.de_q_batch(10), Vec::<i32>::new());
+        assert_eq!(bq.en_q_all(vec![6, 7]), Err(vec![6, 7]));
+    }
+    #[test]
+    fn bq_en_q_all_feeds_batch_consumers_through_capacity() {
+        let bq = Arc::new(BlockingQueue::<i32>::with_capacity(3));
+        let consumers: Vec<_> = (0..3).map(|_| {
+            let q = Arc::clone(&bq);
+            std::thread::spawn(move || {
+                let mut got = Vec::new();
+                loop {
+                    let batch = q.de_q_batch(2);
+                    if batch.is_empty() {
+                        break got;
+                    }
+                    got.extend(batch);
+                }
+            })
+        }).collect();
+        bq.en_q_all(0..100).unwrap();
+        bq.close();
+        let mut all: Vec<i32> = consumers.into_iter()
+            .flat_map(|c| c.join().unwrap())
+            .collect();
+        all.sort_unstable();
+        assert_eq!(all, (0..100).collect::<Vec<_>>());
+    }
+    #[test]
+    fn bq_drain_and_clear_make_room() {
+        let bq = Arc::new(BlockingQueue::<i32>::with_capacity(2));
+        bq.en_q_all(vec![1, 2]).unwrap();
+        let producer = {
+            let q = Arc::clone(&bq);
+            std::thread::spawn(move || q.en_q_all(vec![3, 4]))
+        };
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(bq.drain(), vec![1, 2]);
+        producer.join().unwrap().unwrap();
+        assert_eq!(bq.len(), 2);
+        bq.clear();
+        assert!(bq.is_empty());
+        assert_eq!(bq.drain(), Vec::<i32>::new());
+    }
+    #[test]
+    fn queues_swap_behind_trait() {
+        fn drain_all(q: &dyn Queue<i32>) -> Vec<i32> {
+            q.close();
+            std::iter::from_fn(|| q.de_q()).collect()
+        }
+        let queues: Vec<Box<dyn Queue<i32>>> = vec![
+            Box::new(BlockingQueue::new()),
+            Box::new(PriorityQueue::new(|n: &i32| *n)),
+            Box::new(DelayQueue::new()),
+        ];
+        for q in &queues {
+            for n in [2, 3, 1] {
+                q.en_q(n).unwrap();
+            }
+            assert_eq!(q.len(), 3);
+        }
+        assert_eq!(drain_all(&*queues[0]), [2, 3, 1]);
+        assert_eq!(drain_all(&*queues[1]), [3, 2, 1]);
+        assert_eq!(drain_all(&*queues[2]), [2, 3, 1]);
+        assert!(queues.iter().all(|q| q.is_empty() && q.de_q_timeout(Duration::ZERO).is_none()));
+    }
+    #[test]
+    fn bq_stats_count_traffic_and_times() {
+        let bq = Arc::new(BlockingQueue::<i32>::new().with_stats());
+        assert_eq!(BlockingQueue::<i32>::new().stats(), None);
+        bq.en_q_all(vec![1, 2, 3]).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(bq.de_q_batch(2), vec![1, 2]);
+        let consumer = {
+            let bq = Arc::clone(&bq);
+            std::thread::spawn(move || (bq.de_q(), bq.de_q()))
+        };
+        std::thread::sleep(Duration::from_millis(30));
+        bq.en_q(4).unwrap();
+        assert_eq!(consumer.join().unwrap(), (Some(3), Some(4)));
+        bq.en_q(5).unwrap();
+        bq.clear();
+        let stats = bq.stats().unwrap();
+        assert_eq!((stats.enqueued, stats.dequeued, stats.high_water), (5, 4, 3));
+        assert_eq!(stats.waits, 3);
+        assert!(stats.max_wait >= Duration::from_millis(30));
+        assert!(stats.avg_wait <= stats.max_wait);
+        assert!(stats.max_queued >= Duration::from_millis(20));
+        assert!(stats.avg_queued <= stats.max_queued);
+    }
 }
@@ -0,0 +1,332 @@
+/////////////////////////////////////////////////////////////
+// rust_blocking_queue::lock_free.rs - lock-free backend   //
+//                                                         //
+// Jim Fawcett, https://JimFawcett.github.io, 18 Oct 2026  //
+/////////////////////////////////////////////////////////////
+/*
+   Backend for BlockingQueue::lock_free().  Elements live in
+   crossbeam's SegQueue, or ArrayQueue with a capacity, so
+   producers and consumers don't contend for one Mutex.
+
+   Only threads that must wait touch a lock.  A Parking is a
+   Condvar with a count of sleepers, en_q and de_q skip the
+   notify unless someone is asleep.  The sleeper count and
+   the queue are each checked after the other is changed,
+   with SeqCst fences between, so no wakeup is missed.
+
+   Waiters spin, then yield, SPINS times before parking, as
+   the other side of a busy queue usually acts within that.
+
+   in_flight counts producers between checking closed and
+   pushing.  A consumer sees the queue drained only when it's
+   closed, empty, and no push is in flight, so close behaves
+   as it does for the Mutex backend: nothing queued after
+   close, nothing queued before it lost.
+*/
+
+use crossbeam_queue::{ArrayQueue, SegQueue};
+use std::sync::atomic::{fence, AtomicBool, AtomicUsize, Ordering::SeqCst};
+use std::sync::*;
+use std::time::Instant;
+
+const SPINS:usize = 32;
+
+/*-- element with its en_q time, kept only with stats --*/
+pub(crate) type Slot<T> = (T, Option<Instant>);
+
+#[derive(Debug)]
+enum Items<T> {
+    Unbounded(SegQueue<T>),
+    Bounded(ArrayQueue<T>),
+}
+impl<T> Items<T> {
+    fn push(&self, t: T) -> Result<(), T> {
+        match self {
+            Items::Unbounded(q) => { q.push(t); Ok(()) }
+            Items::Bounded(q) => q.push(t),
+        }
+    }
+    fn pop(&self) -> Option<T> {
+        match self {
+            Items::Unbounded(q) => q.pop(),
+            Items::Bounded(q) => q.pop(),
+        }
+    }
+    fn len(&self) -> usize {
+        match self {
+            Items::Unbounded(q) => q.len(),
+            Items::Bounded(q) => q.len(),
+        }
+    }
+    fn is_empty(&self) -> bool {
+        match self {
+            Items::Unbounded(q) => q.is_empty(),
+            Items::Bounded(q) => q.is_empty(),
+        }
+    }
+    fn is_full(&self) -> bool {
+        match self {
+            Items::Unbounded(_) => false,
+            Items::Bounded(q) => q.is_full(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Parking {
+    lock: Mutex<()>,
+    cv: Condvar,
+    sleepers: AtomicUsize,
+}
+impl Parking {
+    /*-- wait until ready or deadline, returns ready --*/
+    fn park(&self, ready: impl Fn() -> bool, deadline: Option<Instant>) -> bool {
+        let mut guard = self.lock.lock().unwrap();
+        self.sleepers.fetch_add(1, SeqCst);
+        fence(SeqCst);
+        let mut is_ready = ready();
+        while !is_ready {
+            guard = match deadline {
+                None => self.cv.wait(guard).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break;
+                    }
+                    self.cv.wait_timeout(guard, deadline - now).unwrap().0
+                }
+            };
+            is_ready = ready();
+        }
+        self.sleepers.fetch_sub(1, SeqCst);
+        is_ready
+    }
+    fn wake_one(&self) {
+        fence(SeqCst);
+        if self.sleepers.load(SeqCst) > 0 {
+            let _guard = self.lock.lock().unwrap();
+            self.cv.notify_one();
+        }
+    }
+    fn wake_all(&self) {
+        fence(SeqCst);
+        if self.sleepers.load(SeqCst) > 0 {
+            let _guard = self.lock.lock().unwrap();
+            self.cv.notify_all();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct LockFree<T> {
+    items: Items<Slot<T>>,
+    closed: AtomicBool,
+    in_flight: AtomicUsize,
+    not_empty: Parking,
+    not_full: Parking,
+}
+impl<T> LockFree<T> {
+    pub(crate) fn new(capacity: Option<usize>) -> LockFree<T> {
+        let items = match capacity {
+            Some(cap) => Items::Bounded(ArrayQueue::new(cap)),
+            None => Items::Unbounded(SegQueue::new()),
+        };
+        LockFree {
+            items,
+            closed: AtomicBool::new(false),
+            in_flight: AtomicUsize::new(0),
+            not_empty: Parking::default(),
+            not_full: Parking::default(),
+        }
+    }
+    /*-- push, waiting for room if block, returns slot if closed or full --*/
+    pub(crate) fn en_q(&self, slot: Slot<T>, block: bool) -> Result<(), Slot<T>> {
+        self.in_flight.fetch_add(1, SeqCst);
+        let mut slot = slot;
+        let mut spin = 0;
+        let result = loop {
+            if self.closed.load(SeqCst) {
+                break Err(slot);
+            }
+            match self.items.push(slot) {
+                Ok(()) => break Ok(()),
+                Err(back) if !block => break Err(back),
+                Err(back) if spin < SPINS => {
+                    slot = back;
+                    backoff(spin);
+                    spin += 1;
+                }
+                Err(back) => {
+                    slot = back;
+                    self.not_full.park(
+                        || self.closed.load(SeqCst) || !self.items.is_full(), None
+                    );
+                }
+            }
+        };
+        self.in_flight.fetch_sub(1, SeqCst);
+        /* after close, waiting consumers may be waiting on in_flight */
+        if self.closed.load(SeqCst) {
+            self.not_empty.wake_all();
+        } else if result.is_ok() {
+            self.not_empty.wake_one();
+        }
+        result
+    }
+    /*-- pop, waiting until deadline, or forever if None --*/
+    pub(crate) fn de_q(&self, deadline: Option<Instant>) -> Option<Slot<T>> {
+        loop {
+            if let Some(slot) = self.spin_de_q() {
+                return Some(slot);
+            }
+            if self.is_drained() {
+                return None;
+            }
+            let ready = self.not_empty.park(
+                || !self.items.is_empty() || self.is_drained(), deadline
+            );
+            if !ready {
+                /* a wakeup may have come with the timeout, don't strand it */
+                return self.try_de_q();
+            }
+        }
+    }
+    /*-- busy queues refill quickly, try a while before parking --*/
+    fn spin_de_q(&self) -> Option<Slot<T>> {
+        for spin in 0..SPINS {
+            if let Some(slot) = self.try_de_q() {
+                return Some(slot);
+            }
+            backoff(spin);
+        }
+        self.try_de_q()
+    }
+    pub(crate) fn try_de_q(&self) -> Option<Slot<T>> {
+        let slot = self.items.pop()?;
+        self.not_full.wake_one();
+        Some(slot)
+    }
+    pub(crate) fn close(&self) {
+        self.closed.store(true, SeqCst);
+        self.not_empty.wake_all();
+        self.not_full.wake_all();
+    }
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed.load(SeqCst)
+    }
+    /*-- closed, no push in flight, and empty, in that order --*/
+    pub(crate) fn is_drained(&self) -> bool {
+        self.closed.load(SeqCst) && self.in_flight.load(SeqCst) == 0 && self.items.is_empty()
+    }
+    pub(crate) fn len(&self) -> usize {
+        self.items.len()
+    }
+    pub(crate) fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+/*-- spin, then yield, the other side is usually about to act --*/
+fn backoff(spin: usize) {
+    if spin < SPINS / 2 {
+        std::hint::spin_loop();
+    } else {
+        std::thread::yield_now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::time::Duration;
+
+    #[test]
+    fn lf_keeps_blocking_queue_semantics() {
+        let bq = BlockingQueue::<i32>::with_capacity(2).lock_free();
+        assert_eq!(bq.capacity(), Some(2));
+        assert_eq!(bq.try_en_q(1), Ok(()));
+        bq.en_q(2).unwrap();
+        assert_eq!(bq.try_en_q(3), Err(3));
+        assert_eq!(bq.len(), 2);
+        assert_eq!(bq.de_q(), Some(1));
+        assert_eq!(bq.try_de_q(), Some(2));
+        assert_eq!(bq.de_q_timeout(Duration::from_millis(20)), None);
+        bq.en_q_all(vec![4, 5]).unwrap();
+        bq.close();
+        assert_eq!(bq.en_q(6), Err(6));
+        assert_eq!(bq.de_q_batch(5), vec![4, 5]);
+        assert_eq!(bq.de_q(), None);
+    }
+    #[test]
+    fn lf_close_wakes_blocked_consumers_and_producers() {
+        let empty = Arc::new(BlockingQueue::<i32>::new().lock_free());
+        let full = Arc::new(BlockingQueue::<i32>::with_capacity(1).lock_free());
+        full.en_q(1).unwrap();
+        let consumers: Vec<_> = (0..3).map(|_| {
+            let q = Arc::clone(&empty);
+            std::thread::spawn(move || q.de_q())
+        }).collect();
+        let producer = {
+            let q = Arc::clone(&full);
+            std::thread::spawn(move || q.en_q(2))
+        };
+        std::thread::sleep(Duration::from_millis(20));
+        empty.close();
+        full.close();
+        for consumer in consumers {
+            assert_eq!(consumer.join().unwrap(), None);
+        }
+        assert_eq!(producer.join().unwrap(), Err(2));
+        assert_eq!(full.de_q(), Some(1));
+    }
+    #[test]
+    fn lf_many_producers_and_consumers_lose_nothing() {
+        for cap in [None, Some(4)] {
+            let bq = Arc::new(match cap {
+                Some(cap) => BlockingQueue::<usize>::with_capacity(cap),
+                None => BlockingQueue::<usize>::new(),
+            }.lock_free().with_stats());
+            let producers: Vec<_> = (0..4).map(|p| {
+                let q = Arc::clone(&bq);
+                std::thread::spawn(move || {
+                    for i in 0..1000 {
+                        q.en_q(p * 1000 + i).unwrap();
+                    }
+                })
+            }).collect();
+            let consumers: Vec<_> = (0..4).map(|_| {
+                let q = Arc::clone(&bq);
+                std::thread::spawn(move || std::iter::from_fn(|| q.de_q()).collect::<Vec<_>>())
+            }).collect();
+            for producer in producers {
+                producer.join().unwrap();
+            }
+            bq.close();
+            let mut all: Vec<usize> = consumers.into_iter()
+                .flat_map(|c| c.join().unwrap())
+                .collect();
+            all.sort_unstable();
+            assert_eq!(all, (0..4000).collect::<Vec<_>>());
+            let stats = bq.stats().unwrap();
+            assert_eq!((stats.enqueued, stats.dequeued), (4000, 4000));
+        }
+    }
+    #[test]
+    fn lf_works_with_select() {
+        let a = BlockingQueue::<i32>::new().lock_free();
+        let b = Arc::new(BlockingQueue::<i32>::new().lock_free());
+        let producer = {
+            let b = Arc::clone(&b);
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(20));
+                b.en_q(7).unwrap();
+                b.close();
+            })
+        };
+        let mut sel = Select::new(&[&a, &*b]);
+        assert_eq!(sel.select(), Some((1, 7)));
+        a.close();
+        assert_eq!(sel.select(), None);
+        producer.join().unwrap();
+    }
+}
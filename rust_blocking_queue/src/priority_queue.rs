@@ -0,0 +1,277 @@
+/////////////////////////////////////////////////////////////
+// rust_blocking_queue::priority_queue.rs - PriorityQueue  //
+//                                                         //
+// Jim Fawcett, https://JimFawcett.github.io, 18 Oct 2026  //
+/////////////////////////////////////////////////////////////
+/*
+   PriorityQueue<T,K,F> blocks like BlockingQueue<T>, but de_q
+   returns the element with the largest key, computed by the
+   key function or closure F given to new.  Elements with
+   equal keys leave in the order queued.  Key with
+   std::cmp::Reverse to take smallest keys first.
+*/
+
+use crate::Queue;
+use std::cmp::Ordering;
+use std::fmt::{self, Debug};
+use std::collections::BinaryHeap;
+use std::sync::*;
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+struct Entry<T, K> {
+    key: K,
+    seq: u64,
+    item: T,
+}
+impl<T, K: Ord> PartialEq for Entry<T, K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl<T, K: Ord> Eq for Entry<T, K> {}
+impl<T, K: Ord> PartialOrd for Entry<T, K> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+/*-- max-heap: larger key first, then earlier seq --*/
+impl<T, K: Ord> Ord for Entry<T, K> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key.cmp(&other.key)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+#[derive(Debug)]
+struct State<T, K> {
+    heap: BinaryHeap<Entry<T, K>>,
+    next_seq: u64,
+    closed: bool,
+}
+
+/// Thread-safe queue that blocks like BlockingQueue,
+/// de_q returns the element with the largest key
+pub struct PriorityQueue<T, K, F> {
+    q: Mutex<State<T, K>>,
+    cv: Condvar,
+    not_full: Condvar,
+    capacity: Option<usize>,
+    key: F,
+}
+/*-- closures aren't Debug, so key is left out --*/
+impl<T: Debug, K: Debug, F> Debug for PriorityQueue<T, K, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PriorityQueue")
+            .field("q", &self.q)
+            .field("capacity", &self.capacity)
+            .finish_non_exhaustive()
+    }
+}
+impl<T, K: Ord, F: Fn(&T) -> K> PriorityQueue<T, K, F> {
+    /// Create empty, unbounded queue ordered by key
+    pub fn new(key: F) -> Self {
+        Self {
+            q: Mutex::new(State { heap: BinaryHeap::new(), next_seq: 0, closed: false }),
+            cv: Condvar::new(),
+            not_full: Condvar::new(),
+            capacity: None,
+            key,
+        }
+    }
+    /// Create empty queue ordered by key, holding at most cap elements
+    /// - panics if cap is zero
+    pub fn with_capacity(cap: usize, key: F) -> Self {
+        assert!(cap > 0, "PriorityQueue capacity must be at least 1");
+        Self {
+            q: Mutex::new(State {
+                heap: BinaryHeap::with_capacity(cap), next_seq: 0, closed: false
+            }),
+            cv: Condvar::new(),
+            not_full: Condvar::new(),
+            capacity: Some(cap),
+            key,
+        }
+    }
+    /// push input, waiting for room if full
+    /// - returns input to caller if queue is closed
+    pub fn en_q(&self, t:T) -> Result<(), T> {
+        let mut lq = self.q.lock().unwrap();
+        while !lq.closed && self.is_full(&lq) {
+            lq = self.not_full.wait(lq).unwrap();
+        }
+        if lq.closed {
+            return Err(t);
+        }
+        self.push(&mut lq, t);
+        Ok(())
+    }
+    /// push input once fewer than limit elements are queued,
+    /// e.g., to keep one producer from filling a shared queue
+    /// - returns input to caller if queue is closed
+    pub fn en_q_below(&self, t:T, limit: usize) -> Result<(), T> {
+        let mut lq = self.q.lock().unwrap();
+        while !lq.closed && (self.is_full(&lq) || lq.heap.len() >= limit.max(1)) {
+            lq = self.not_full.wait(lq).unwrap();
+        }
+        if lq.closed {
+            return Err(t);
+        }
+        self.push(&mut lq, t);
+        Ok(())
+    }
+    /// push input if there's room and the queue is open,
+    /// else return it to the caller
+    pub fn try_en_q(&self, t:T) -> Result<(), T> {
+        let mut lq = self.q.lock().unwrap();
+        if lq.closed || self.is_full(&lq) {
+            return Err(t);
+        }
+        self.push(&mut lq, t);
+        Ok(())
+    }
+    /// pop element with largest key, waiting if empty
+    /// - None once queue is closed and drained
+    pub fn de_q(&self) -> Option<T> {
+        let mut lq = self.q.lock().unwrap();
+        loop {
+            if let Some(t) = self.pop(&mut lq) {
+                return Some(t);
+            }
+            if lq.closed {
+                return None;
+            }
+            lq = self.cv.wait(lq).unwrap();
+        }
+    }
+    /// pop element with largest key if there is one
+    pub fn try_de_q(&self) -> Option<T> {
+        let mut lq = self.q.lock().unwrap();
+        self.pop(&mut lq)
+    }
+    /// pop element, waiting at most timeout for one to arrive
+    pub fn de_q_timeout(&self, timeout: Duration) -> Option<T> {
+        match Instant::now().checked_add(timeout) {
+            Some(deadline) => self.de_q_deadline(deadline),
+            None => self.de_q(),
+        }
+    }
+    /// pop element, waiting until deadline for one to arrive
+    pub fn de_q_deadline(&self, deadline: Instant) -> Option<T> {
+        let mut lq = self.q.lock().unwrap();
+        loop {
+            if let Some(t) = self.pop(&mut lq) {
+                return Some(t);
+            }
+            let now = Instant::now();
+            if lq.closed || now >= deadline {
+                return None;
+            }
+            lq = self.cv.wait_timeout(lq, deadline - now).unwrap().0;
+        }
+    }
+    /// key of the element de_q would return
+    pub fn peek_key(&self) -> Option<K> where K: Clone {
+        self.q.lock().unwrap().heap.peek().map(|entry| entry.key.clone())
+    }
+    /// no more elements will be accepted, wakes all waiters
+    pub fn close(&self) {
+        self.q.lock().unwrap().closed = true;
+        self.cv.notify_all();
+        self.not_full.notify_all();
+    }
+    pub fn is_closed(&self) -> bool {
+        self.q.lock().unwrap().closed
+    }
+    pub fn len(&self) -> usize {
+        self.q.lock().unwrap().heap.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.q.lock().unwrap().heap.is_empty()
+    }
+    pub fn capacity(&self) -> Option<usize> {
+        self.capacity
+    }
+    fn push(&self, lq: &mut State<T, K>, item: T) {
+        let seq = lq.next_seq;
+        lq.next_seq += 1;
+        lq.heap.push(Entry { key: (self.key)(&item), seq, item });
+        self.cv.notify_one();
+    }
+    fn pop(&self, lq: &mut State<T, K>) -> Option<T> {
+        let entry = lq.heap.pop()?;
+        /* waiters may want room below different limits */
+        self.not_full.notify_all();
+        Some(entry.item)
+    }
+    fn is_full(&self, lq: &State<T, K>) -> bool {
+        self.capacity.is_some_and(|cap| lq.heap.len() >= cap)
+    }
+}
+impl<T, K: Ord, F: Fn(&T) -> K> Queue<T> for PriorityQueue<T, K, F> {
+    fn en_q(&self, t: T) -> Result<(), T> { PriorityQueue::en_q(self, t) }
+    fn try_en_q(&self, t: T) -> Result<(), T> { PriorityQueue::try_en_q(self, t) }
+    fn de_q(&self) -> Option<T> { PriorityQueue::de_q(self) }
+    fn try_de_q(&self) -> Option<T> { PriorityQueue::try_de_q(self) }
+    fn de_q_deadline(&self, deadline: Instant) -> Option<T> {
+        PriorityQueue::de_q_deadline(self, deadline)
+    }
+    fn close(&self) { PriorityQueue::close(self) }
+    fn is_closed(&self) -> bool { PriorityQueue::is_closed(self) }
+    fn len(&self) -> usize { PriorityQueue::len(self) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn pq_largest_key_first_fifo_within() {
+        let pq = PriorityQueue::new(|s: &(u8, &str)| s.0);
+        for item in [(1, "a"), (1, "b"), (5, "c"), (9, "d"), (1, "e")] {
+            pq.en_q(item).unwrap();
+        }
+        assert_eq!(pq.peek_key(), Some(9));
+        let order: Vec<&str> = std::iter::from_fn(|| pq.try_de_q()).map(|s| s.1).collect();
+        assert_eq!(order, ["d", "c", "a", "b", "e"]);
+    }
+    #[test]
+    fn pq_key_closure_may_capture() {
+        let rank = std::collections::HashMap::from([("low", 1), ("high", 2)]);
+        let pq = PriorityQueue::new(move |s: &&str| rank.get(s).copied());
+        for item in ["low", "unknown", "high"] {
+            pq.en_q(item).unwrap();
+        }
+        let order: Vec<&str> = std::iter::from_fn(|| pq.try_de_q()).collect();
+        assert_eq!(order, ["high", "low", "unknown"]);
+        assert!(format!("{:?}", pq).starts_with("PriorityQueue"));
+    }
+    #[test]
+    fn pq_reverse_key_capacity_and_close() {
+        let pq = PriorityQueue::with_capacity(2, |n: &i32| std::cmp::Reverse(*n));
+        pq.en_q(7).unwrap();
+        pq.en_q(3).unwrap();
+        assert_eq!(pq.try_en_q(5), Err(5));
+        assert_eq!(pq.de_q(), Some(3));
+        pq.close();
+        assert_eq!(pq.en_q(1), Err(1));
+        assert_eq!(pq.de_q_timeout(Duration::from_secs(10)), Some(7));
+        assert_eq!(pq.de_q(), None);
+    }
+    #[test]
+    fn pq_en_q_below_waits_for_room() {
+        let pq = Arc::new(PriorityQueue::new(|n: &i32| *n));
+        pq.en_q(1).unwrap();
+        pq.en_q(2).unwrap();
+        let producer = {
+            let pq = Arc::clone(&pq);
+            std::thread::spawn(move || pq.en_q_below(3, 2))
+        };
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(pq.len(), 2);
+        assert_eq!(pq.de_q(), Some(2));
+        assert_eq!(producer.join().unwrap(), Ok(()));
+        assert_eq!(pq.len(), 2);
+        pq.close();
+        assert_eq!(pq.en_q_below(4, 10), Err(4));
+    }
+}
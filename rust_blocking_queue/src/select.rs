@@ -0,0 +1,182 @@
+/////////////////////////////////////////////////////////////
+// rust_blocking_queue::select.rs - wait on several queues //
+//                                                         //
+// Jim Fawcett, https://JimFawcett.github.io, 18 Oct 2026  //
+/////////////////////////////////////////////////////////////
+/*
+   Select<T> waits on several BlockingQueue<T>s at once, e.g.,
+   a worker's control and data queues, and returns the first
+   element found with the index of its queue.
+
+   While a Select lives it registers a Signal with each of its
+   queues, fired whenever one gets an element or is closed,
+   so select blocks without polling.  Scans start just past
+   the queue that fired last, so a busy queue can't starve
+   the others.
+*/
+
+use crate::BlockingQueue;
+use std::sync::*;
+use std::time::{Duration, Instant};
+
+/*-- set by queues, cleared by Select before each scan --*/
+#[derive(Debug, Default)]
+pub(crate) struct Signal {
+    fired: Mutex<bool>,
+    cv: Condvar,
+}
+impl Signal {
+    pub(crate) fn fire(&self) {
+        *self.fired.lock().unwrap() = true;
+        self.cv.notify_all();
+    }
+}
+
+#[derive(Debug)]
+/// Takes elements from whichever of several queues has one
+pub struct Select<'a, T> {
+    queues: Vec<&'a BlockingQueue<T>>,
+    signal: Arc<Signal>,
+    next: usize,
+}
+impl<'a, T> Select<'a, T> {
+    /// Select over queues, index i of results refers to queues[i]
+    pub fn new(queues: &[&'a BlockingQueue<T>]) -> Self {
+        let signal = Arc::new(Signal::default());
+        for q in queues {
+            q.watch(&signal);
+        }
+        Self { queues: queues.to_vec(), signal, next: 0 }
+    }
+    /// (index, element) from a queue with an element, waiting if all empty
+    /// - None once every queue is closed and drained
+    pub fn select(&mut self) -> Option<(usize, T)> {
+        loop {
+            *self.signal.fired.lock().unwrap() = false;
+            if let Some(found) = self.scan() {
+                return found;
+            }
+            let mut fired = self.signal.fired.lock().unwrap();
+            while !*fired {
+                fired = self.signal.cv.wait(fired).unwrap();
+            }
+        }
+    }
+    /// (index, element) if any queue has an element now
+    pub fn try_select(&mut self) -> Option<(usize, T)> {
+        self.scan().flatten()
+    }
+    /// select, waiting at most timeout
+    pub fn select_timeout(&mut self, timeout: Duration) -> Option<(usize, T)> {
+        match Instant::now().checked_add(timeout) {
+            Some(deadline) => self.select_deadline(deadline),
+            None => self.select(),
+        }
+    }
+    /// select, waiting until deadline at most
+    pub fn select_deadline(&mut self, deadline: Instant) -> Option<(usize, T)> {
+        loop {
+            *self.signal.fired.lock().unwrap() = false;
+            if let Some(found) = self.scan() {
+                return found;
+            }
+            let mut fired = self.signal.fired.lock().unwrap();
+            while !*fired {
+                let now = Instant::now();
+                if now >= deadline {
+                    return None;
+                }
+                fired = self.signal.cv.wait_timeout(fired, deadline - now).unwrap().0;
+            }
+        }
+    }
+    /*-----------------------------------------------------
+      One pass over the queues, starting after the last
+      that fired:
+      - Some(Some(..)) found an element
+      - Some(None) every queue is closed and drained
+      - None nothing yet, caller waits for the signal
+    */
+    fn scan(&mut self) -> Option<Option<(usize, T)>> {
+        let n = self.queues.len();
+        let mut all_done = true;
+        for i in (0..n).map(|k| (self.next + k) % n) {
+            if let Some(t) = self.queues[i].try_de_q() {
+                self.next = (i + 1) % n;
+                return Some(Some((i, t)));
+            }
+            all_done &= self.queues[i].is_drained();
+        }
+        if all_done {
+            return Some(None);
+        }
+        None
+    }
+}
+impl<'a, T> Drop for Select<'a, T> {
+    fn drop(&mut self) {
+        for q in &self.queues {
+            q.unwatch(&self.signal);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn select_reports_queue_that_fired() {
+        let control = Arc::new(BlockingQueue::<String>::new());
+        let data = Arc::new(BlockingQueue::<String>::new());
+        let producer = {
+            let control = Arc::clone(&control);
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(20));
+                control.en_q("stop".to_string()).unwrap();
+            })
+        };
+        let mut sel = Select::new(&[&*data, &*control]);
+        assert_eq!(sel.select(), Some((1, "stop".to_string())));
+        producer.join().unwrap();
+        assert_eq!(sel.select_timeout(Duration::from_millis(20)), None);
+        data.close();
+        control.close();
+        assert_eq!(sel.select(), None);
+    }
+    #[test]
+    fn select_takes_turns_between_busy_queues() {
+        let a = BlockingQueue::<i32>::new();
+        let b = BlockingQueue::<i32>::new();
+        a.en_q_all(vec![1, 2, 3]).unwrap();
+        b.en_q_all(vec![10, 20]).unwrap();
+        let mut sel = Select::new(&[&a, &b]);
+        let order: Vec<_> = std::iter::from_fn(|| sel.try_select()).collect();
+        assert_eq!(order, [(0, 1), (1, 10), (0, 2), (1, 20), (0, 3)]);
+    }
+    #[test]
+    fn select_consumes_bounded_batch_producer() {
+        let q = Arc::new(BlockingQueue::<i32>::with_capacity(2));
+        let other = BlockingQueue::<i32>::new();
+        let producer = {
+            let q = Arc::clone(&q);
+            std::thread::spawn(move || {
+                q.en_q_all(0..10).unwrap();
+                q.close();
+            })
+        };
+        let mut sel = Select::new(&[&*q, &other]);
+        other.close();
+        let got: Vec<i32> = std::iter::from_fn(|| sel.select()).map(|(_, n)| n).collect();
+        assert_eq!(got, (0..10).collect::<Vec<_>>());
+        producer.join().unwrap();
+    }
+    #[test]
+    fn dropped_select_unregisters() {
+        let q = BlockingQueue::<i32>::new();
+        {
+            let _sel = Select::new(&[&q]);
+            assert_eq!(q.q.lock().unwrap().watchers.len(), 1);
+        }
+        assert!(q.q.lock().unwrap().watchers.is_empty());
+    }
+}
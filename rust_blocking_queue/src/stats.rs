@@ -0,0 +1,88 @@
+/////////////////////////////////////////////////////////////
+// rust_blocking_queue::stats.rs - queue instrumentation   //
+//                                                         //
+// Jim Fawcett, https://JimFawcett.github.io, 18 Oct 2026  //
+/////////////////////////////////////////////////////////////
+/*
+   Counters kept by a BlockingQueue made with with_stats():
+   - elements enqueued and dequeued, and the high-water mark
+   - time blocking dequeuers waited, from call to return
+   - time elements spent queued, from en_q to de_q
+   Counters are atomics, updated as the queue works, so
+   BlockingQueue::stats reads a QueueStats snapshot without
+   taking the queue's lock.  Fields are read one by one, a
+   snapshot of a busy queue may be a few operations skewed.
+*/
+
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+/// Snapshot of a queue's counters, see BlockingQueue::stats
+pub struct QueueStats {
+    pub enqueued: u64,
+    pub dequeued: u64,
+    /// most elements queued at once
+    pub high_water: usize,
+    /// number of blocking de_q calls
+    pub waits: u64,
+    pub avg_wait: Duration,
+    pub max_wait: Duration,
+    /// time dequeued elements spent queued
+    pub avg_queued: Duration,
+    pub max_queued: Duration,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Stats {
+    enqueued: AtomicU64,
+    dequeued: AtomicU64,
+    high_water: AtomicUsize,
+    waits: AtomicU64,
+    wait_total_ns: AtomicU64,
+    wait_max_ns: AtomicU64,
+    queued_total_ns: AtomicU64,
+    queued_max_ns: AtomicU64,
+}
+impl Stats {
+    pub(crate) fn record_en_q(&self, len: usize) {
+        self.enqueued.fetch_add(1, Ordering::Relaxed);
+        self.high_water.fetch_max(len, Ordering::Relaxed);
+    }
+    pub(crate) fn record_de_q(&self, queued: Duration) {
+        let ns = nanos(queued);
+        self.dequeued.fetch_add(1, Ordering::Relaxed);
+        self.queued_total_ns.fetch_add(ns, Ordering::Relaxed);
+        self.queued_max_ns.fetch_max(ns, Ordering::Relaxed);
+    }
+    pub(crate) fn record_wait(&self, waited: Duration) {
+        let ns = nanos(waited);
+        self.waits.fetch_add(1, Ordering::Relaxed);
+        self.wait_total_ns.fetch_add(ns, Ordering::Relaxed);
+        self.wait_max_ns.fetch_max(ns, Ordering::Relaxed);
+    }
+    pub(crate) fn snapshot(&self) -> QueueStats {
+        let dequeued = self.dequeued.load(Ordering::Relaxed);
+        let waits = self.waits.load(Ordering::Relaxed);
+        QueueStats {
+            enqueued: self.enqueued.load(Ordering::Relaxed),
+            dequeued,
+            high_water: self.high_water.load(Ordering::Relaxed),
+            waits,
+            avg_wait: average(&self.wait_total_ns, waits),
+            max_wait: Duration::from_nanos(self.wait_max_ns.load(Ordering::Relaxed)),
+            avg_queued: average(&self.queued_total_ns, dequeued),
+            max_queued: Duration::from_nanos(self.queued_max_ns.load(Ordering::Relaxed)),
+        }
+    }
+}
+fn nanos(d: Duration) -> u64 {
+    u64::try_from(d.as_nanos()).unwrap_or(u64::MAX)
+}
+fn average(total_ns: &AtomicU64, count: u64) -> Duration {
+    match count {
+        0 => Duration::ZERO,
+        n => Duration::from_nanos(total_ns.load(Ordering::Relaxed) / n),
+    }
+}
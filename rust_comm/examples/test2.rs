@@ -37,7 +37,7 @@ fn start_client(
         for i in 0..n {
             /*-- used to test error handling --*/
             if sd && i == n-1 {
-                let mut msg = Message::new(TYPE_SIZE + CONTENT_SIZE);
+                let mut msg = Message::new(HEADER_SIZE);
                 msg.set_type(MessageType::QUIT as u8);
                 conn.post_message(msg);
                 return;
@@ -49,10 +49,11 @@ fn start_client(
             print!("\n  posting msg:  {:?}", s);
             Log::write(&format!("\n  message size: {:?}", msg.len()));
             conn.post_message(msg);
-            let msg = conn.get_message();
-            print!("\n  received msg: {:?}", msg.get_content_str().unwrap());
+            if let Some(msg) = conn.get_message() {
+                print!("\n  received msg: {:?}", msg.get_content_str().unwrap());
+            }
         }
-        let mut msg = Message::new(TYPE_SIZE + CONTENT_SIZE);
+        let mut msg = Message::new(HEADER_SIZE);
         msg.set_type(MessageType::END as u8);
         print!("\n  posting END message");
         conn.post_message(msg);
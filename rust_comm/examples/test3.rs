@@ -78,7 +78,10 @@ fn client_wait_for_reply<L: Logger>(
                 )
             );
             conn.post_message(msg.clone());
-            let msg = conn.get_message();
+            let msg = match conn.get_message() {
+                Some(msg) => msg,
+                None => break,
+            };
             L::write(
                 &format!(
                     "\n  received msg: {:?}", 
@@ -138,13 +141,16 @@ fn client_no_wait_for_reply<L: Logger>(
             );
             sconn1.post_message(msg.clone());
         }
-        let mut msg = Message::new(TYPE_SIZE + CONTENT_SIZE);
+        let mut msg = Message::new(HEADER_SIZE);
         msg.set_type(MessageType::END as u8);
         sconn1.post_message(msg);
     });
     let handle = std::thread::spawn(move || {
         for _i in 0..num_msgs {
-            let msg = sconn2.get_message();
+            let msg = match sconn2.get_message() {
+                Some(msg) => msg,
+                None => break,
+            };
             L::write(
                 &format!(
                     "\n  received msg: {:?}", 
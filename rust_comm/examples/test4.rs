@@ -69,7 +69,10 @@ fn client_wait_for_reply<L: Logger>(
                 )
             );
             conn.post_message(msg.clone());
-            let _msg = conn.get_message();
+            let _msg = match conn.get_message() {
+                Some(msg) => msg,
+                None => break,
+            };
             L::write(
                 &format!(
                     "\n  received msg: {:?}", 
@@ -125,7 +128,10 @@ fn client_no_wait_for_reply<L: Logger>(
     });
     let handle = std::thread::Builder::new().name("second".to_string()).spawn(move || {
         for _i in 0..num_msgs {
-            let msg = sconn2.get_message();
+            let msg = match sconn2.get_message() {
+                Some(msg) => msg,
+                None => break,
+            };
             L::write(
                 &format!(
                     "\n  received msg: {:?}", 
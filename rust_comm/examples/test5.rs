@@ -0,0 +1,137 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::test5.rs - batched send benchmark            //
+//                                                         //
+// Jim Fawcett, https://JimFawcett.github.io, 18 Oct 2026  //
+/////////////////////////////////////////////////////////////
+/*
+   Compares two ways of sending a burst of small messages:
+   - flush per message: the previous Connector send thread,
+     reproduced here, with every message marked FLUSH as
+     callers did for latency, one write and flush per message
+   - Connector: send thread drains its queue, writes each
+     batch with one vectored write, and flushes only when
+     the queue runs dry
+   Both wait for every reply before stopping the timer, and
+   both turn off compression and checksums so only the way
+   frames are written differs.
+*/
+
+use std::io::{BufReader, BufWriter};
+use std::net::TcpStream;
+use std::sync::Arc;
+
+use rust_message::*;
+use rust_traits::*;
+use rust_comm_processing::*;
+use rust_comm_logger::*;
+use rust_blocking_queue::*;
+use rust_comm::*;
+use rust_timer::*;
+
+type Log = MuteLog;
+type M = Message;
+type P = CommProcessing<Log>;
+
+fn end_msg() -> Message {
+    let mut msg = Message::create_msg_header_only();
+    msg.set_type(MessageType::END as u8);
+    msg
+}
+fn plain_hello() -> Hello {
+    Hello { features: 0, ..Hello::default() }
+}
+/*-- returns elapsed microseconds --*/
+fn flush_per_message(addr: &'static str, num_msgs: usize, sz_bytes: usize)
+    -> std::io::Result<u128>
+{
+    let stream = TcpStream::connect(addr)?;
+    let mut buf_writer = BufWriter::new(stream.try_clone()?);
+    let mut buf_reader = BufReader::new(stream);
+    client_handshake(&plain_hello(), &mut buf_reader, &mut buf_writer)?;
+    let snd_queue = Arc::new(BlockingQueue::<Message>::new());
+    let rcv_queue = Arc::new(BlockingQueue::<Message>::new());
+
+    /*-- send and receive threads as the Connector used to run them --*/
+    let sq = Arc::clone(&snd_queue);
+    let sender = std::thread::spawn(move || -> std::io::Result<()> {
+        while let Some(msg) = sq.de_q() {
+            P::buf_send_message(&msg, &mut buf_writer)?;
+            if msg.get_type() == MessageType::END as u8 {
+                break;
+            }
+        }
+        Ok(())
+    });
+    let rq = Arc::clone(&rcv_queue);
+    let _ = std::thread::spawn(move || {
+        while let Ok(msg) = P::buf_recv_message(&mut buf_reader) {
+            let _ = rq.en_q(msg);
+        }
+    });
+    let mut msg = Message::create_msg_bytes_fit(&vec![0;sz_bytes]);
+    msg.set_type(MessageType::FLUSH as u8);
+
+    let mut tmr = StopWatch::new();
+    tmr.start();
+    for _ in 0..num_msgs {
+        let _ = snd_queue.en_q(msg.clone());
+    }
+    for _ in 0..num_msgs {
+        let _reply = rcv_queue.de_q();
+    }
+    let _ = tmr.stop();
+    let _ = snd_queue.en_q(end_msg());
+    let _ = sender.join();
+    Ok(tmr.elapsed_micros())
+}
+fn batched(addr: &'static str, num_msgs: usize, sz_bytes: usize) -> u128 {
+    let conn = Connector::<P,M,Log>::with_hello(addr, plain_hello()).unwrap();
+    let mut msg = Message::create_msg_bytes_fit(&vec![0;sz_bytes]);
+    msg.set_type(MessageType::TEXT as u8);
+
+    let mut tmr = StopWatch::new();
+    tmr.start();
+    for _ in 0..num_msgs {
+        conn.post_message(msg.clone());
+    }
+    for _ in 0..num_msgs {
+        let _reply = conn.get_message();
+    }
+    let _ = tmr.stop();
+    conn.post_message(end_msg());
+    tmr.elapsed_micros()
+}
+fn display_test_data(name: &str, et:u128, num_msgs:usize) {
+    let elapsed_time_sec = 1.0e-6 * et.max(1) as f64;
+    print!(
+        "\n      {:<18} elapsed microsec {:>8}, messages/second {:>10.2}",
+        name, et, num_msgs as f64 / elapsed_time_sec
+    );
+}
+
+fn main() {
+
+    print!("\n  -- Demo rust_comm: test5");
+    print!("\n  -- batched, vectored sends vs flush per message\n");
+
+    let addr = "127.0.0.1:8089";
+    let mut lsnr = Listener::<P,Log>::new();
+    let handle = match lsnr.start(addr) {
+        Ok(handle) => handle,
+        Err(_) => return,
+    };
+    let num_msgs = 20_000;
+    for sz_bytes in [0, 64, 1024] {
+        print!("\n  -- {} msgs, {} bytes content per msg", num_msgs, sz_bytes);
+        match flush_per_message(addr, num_msgs, sz_bytes) {
+            Ok(et) => display_test_data("flush per message", et, num_msgs),
+            Err(e) => print!("\n      flush per message failed: {}", e),
+        }
+        let et = batched(addr, num_msgs, sz_bytes);
+        display_test_data("batched", et, num_msgs);
+        println!();
+    }
+    lsnr.stop();
+    let _ = handle.join();
+    print!("\n  That's all Folks!\n\n");
+}
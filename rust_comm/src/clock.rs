@@ -0,0 +1,55 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::clock.rs - time source for message deadlines //
+//                                                         //
+// Jim Fawcett, https://JimFawcett.github.io, 18 Oct 2026  //
+/////////////////////////////////////////////////////////////
+/*
+   Clocks implementing rust_traits::Clock:
+   - SystemClock reads the system time, used by default
+   - ManualClock only moves when told to, so tests can
+     expire messages without sleeping
+   Deadlines compare wall clock times from both ends of a
+   link, so hosts with deadlines should keep clocks synced.
+*/
+
+use rust_traits::Clock;
+
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| u64::try_from(d.as_millis()).unwrap_or(u64::MAX))
+            .unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now: AtomicU64,
+}
+impl ManualClock {
+    pub fn new(now_ms: u64) -> ManualClock {
+        ManualClock { now: AtomicU64::new(now_ms) }
+    }
+    pub fn set(&self, now_ms: u64) {
+        self.now.store(now_ms, Ordering::SeqCst);
+    }
+    pub fn advance(&self, by: Duration) {
+        let by_ms = u64::try_from(by.as_millis()).unwrap_or(u64::MAX);
+        let _ = self.now.fetch_update(
+            Ordering::SeqCst, Ordering::SeqCst, |now| Some(now.saturating_add(by_ms))
+        );
+    }
+}
+impl Clock for ManualClock {
+    fn now_millis(&self) -> u64 {
+        self.now.load(Ordering::SeqCst)
+    }
+}
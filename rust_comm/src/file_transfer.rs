@@ -0,0 +1,410 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::file_transfer.rs - send files over Connector //
+//                                                         //
+// Jim Fawcett, https://JimFawcett.github.io, 18 Oct 2026  //
+/////////////////////////////////////////////////////////////
+/*
+   Protocol, all integers big-endian:
+     FILE_BEGIN  size u64, offset u64, file name
+                 - offset is where sender would like to start,
+                   sink answers with the offset it accepts,
+                   the length of a partial file it already has
+     FILE_CHUNK  offset u64, data
+                 - no reply
+     FILE_END    sha256 of the whole file, 32 bytes
+                 - sink checks digest, then renames the partial
+                   file into its sink directory
+
+   FILE_BEGIN and FILE_END are sent with Connector::request,
+   sink replies with a message of the same type and request
+   id holding a FileReply.  Sink refuses files larger than
+   its quota, CommOptions::sink_quota.
+
+   Partial files are kept as <name>.part until verified, so an
+   interrupted transfer resumes where it left off.
+*/
+
+use rust_traits::*;
+use rust_message::*;
+use crate::SendQueue;
+use sha2::{Sha256, Digest};
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write, Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/*-- most file data per FILE_CHUNK --*/
+pub const FILE_CHUNK_SIZE: usize = 64 * 1024;
+pub const FILE_CHUNK_HEADER_SIZE: usize = 8;
+pub const SHA256_SIZE: usize = 32;
+/*-- chunks allowed in the send queue, keeps big files out of memory --*/
+pub const FILE_SEND_WINDOW: usize = 8;
+/*-- longest wait for the sink to answer FILE_BEGIN or FILE_END --*/
+pub const FILE_REPLY_TIMEOUT: Duration = Duration::from_secs(60);
+/*-- largest file a sink accepts unless configured otherwise --*/
+pub const DEFAULT_SINK_QUOTA: u64 = 1 << 32;
+
+pub fn is_file_type(msg_type: u8) -> bool {
+    msg_type == MessageType::FILE_BEGIN as u8
+        || msg_type == MessageType::FILE_CHUNK as u8
+        || msg_type == MessageType::FILE_END as u8
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> Option<u64> {
+    let field = bytes.get(offset..offset + 8)?;
+    let mut dst = [0u8; 8];
+    dst.copy_from_slice(field);
+    Some(u64::from_be_bytes(dst))
+}
+fn build_msg<M: Msg>(msg_type: u8, body: Vec<u8>) -> M {
+    let mut msg = M::new(HEADER_SIZE);
+    msg.set_type(msg_type);
+    msg.set_body(Bytes::from(body));
+    msg
+}
+fn invalid(text: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, text.to_string())
+}
+
+/*---------------------------------------------------------
+  FileBegin - announces a file, asks where to start
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileBegin {
+    pub size: u64,
+    pub offset: u64,
+    pub name: String,
+}
+impl FileBegin {
+    pub fn to_msg<M: Msg>(&self) -> M {
+        let mut body = Vec::with_capacity(16 + self.name.len());
+        body.extend_from_slice(&self.size.to_be_bytes());
+        body.extend_from_slice(&self.offset.to_be_bytes());
+        body.extend_from_slice(self.name.as_bytes());
+        build_msg(MessageType::FILE_BEGIN as u8, body)
+    }
+    pub fn from_msg<M: Msg>(msg: &M) -> Result<FileBegin> {
+        let body = msg.get_content_bytes();
+        let size = u64_at(body, 0).ok_or_else(|| invalid("short FILE_BEGIN"))?;
+        let offset = u64_at(body, 8).ok_or_else(|| invalid("short FILE_BEGIN"))?;
+        let name = std::str::from_utf8(&body[16..])
+            .map_err(|_| invalid("FILE_BEGIN name is not utf-8"))?;
+        Ok(FileBegin { size, offset, name: name.to_string() })
+    }
+}
+
+/*---------------------------------------------------------
+  FileReply - sink's answer to FILE_BEGIN and FILE_END
+  - offset is where to resume, meaningful for FILE_BEGIN
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileReply {
+    pub ok: bool,
+    pub offset: u64,
+    pub detail: String,
+}
+impl FileReply {
+    pub fn ok(offset: u64) -> FileReply {
+        FileReply { ok: true, offset, detail: String::new() }
+    }
+    pub fn failed(detail: &str) -> FileReply {
+        FileReply { ok: false, offset: 0, detail: detail.to_string() }
+    }
+    pub fn to_msg<M: Msg>(&self, msg_type: u8) -> M {
+        let mut body = Vec::with_capacity(9 + self.detail.len());
+        body.push(if self.ok { 0 } else { 1 });
+        body.extend_from_slice(&self.offset.to_be_bytes());
+        body.extend_from_slice(self.detail.as_bytes());
+        build_msg(msg_type, body)
+    }
+    pub fn from_msg<M: Msg>(msg: &M) -> Result<FileReply> {
+        let body = msg.get_content_bytes();
+        let offset = u64_at(body, 1).ok_or_else(|| invalid("short file reply"))?;
+        Ok(FileReply {
+            ok: body[0] == 0,
+            offset,
+            detail: String::from_utf8_lossy(&body[9..]).into_owned(),
+        })
+    }
+    /*-- failed reply as io error for the sender --*/
+    pub fn into_result(self) -> Result<u64> {
+        if self.ok {
+            Ok(self.offset)
+        }
+        else {
+            Err(Error::other(format!("file sink: {}", self.detail)))
+        }
+    }
+}
+
+pub fn file_chunk<M: Msg>(offset: u64, data: &[u8]) -> M {
+    let mut body = Vec::with_capacity(FILE_CHUNK_HEADER_SIZE + data.len());
+    body.extend_from_slice(&offset.to_be_bytes());
+    body.extend_from_slice(data);
+    build_msg(MessageType::FILE_CHUNK as u8, body)
+}
+pub fn file_end<M: Msg>(digest: &[u8; SHA256_SIZE]) -> M {
+    build_msg(MessageType::FILE_END as u8, digest.to_vec())
+}
+
+/*-- sha256 of first len bytes of file, hasher continues from there --*/
+pub fn hash_prefix(file: &mut File, len: u64) -> Result<Sha256> {
+    let mut hasher = Sha256::new();
+    let mut left = len;
+    let mut buf = vec![0u8; FILE_CHUNK_SIZE];
+    file.seek(SeekFrom::Start(0))?;
+    while left > 0 {
+        let want = (left as usize).min(buf.len());
+        file.read_exact(&mut buf[..want])?;
+        hasher.update(&buf[..want]);
+        left -= want as u64;
+    }
+    Ok(hasher)
+}
+
+/*---------------------------------------------------------
+  Sending end, used by Connector::send_file_from
+  - request posts a message and waits for its reply, see
+    Connector::request
+  - chunks fit in one frame of fragment_size, fragmented
+    chunks could complete out of order
+  - waits for room in snd while more than FILE_SEND_WINDOW
+    messages are queued
+*/
+pub(crate) fn send_file<M, F>(
+    snd: &SendQueue<M>, request: F,
+    path: &Path, offset: u64, fragment_size: usize
+) -> Result<u64> 
+where
+    M: Msg + Clone + Send + Default,
+    F: Fn(M) -> Result<M>,
+{
+    let chunk_size = fragment_size.saturating_sub(FILE_CHUNK_HEADER_SIZE)
+        .clamp(1, FILE_CHUNK_SIZE);
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let name = path.file_name()
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "path has no file name"))?
+        .to_string_lossy().into_owned();
+
+    let begin = FileBegin { size, offset: offset.min(size), name };
+    let start = FileReply::from_msg(&request(begin.to_msg())?)?
+        .into_result()?
+        .min(size);
+
+    /*-- digest covers whole file, including part sink already has --*/
+    let mut hasher = hash_prefix(&mut file, start)?;
+    let mut buf = vec![0u8; chunk_size];
+    let mut next = start;
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        snd.en_q_below(file_chunk(next, &buf[..n]), FILE_SEND_WINDOW);
+        next += n as u64;
+    }
+    let digest: [u8; SHA256_SIZE] = hasher.finalize().into();
+    FileReply::from_msg(&request(file_end(&digest))?)?.into_result()?;
+    Ok(next - start)
+}
+
+/*---------------------------------------------------------
+  FileSink - receiving end, one per client session
+  - holds one transfer at a time, FILE_BEGIN drops any
+    transfer in progress, so a Connector sends files one
+    after another
+*/
+#[derive(Debug)]
+struct Incoming {
+    file: File,
+    part: PathBuf,
+    dest: PathBuf,
+    size: u64,
+    next: u64,
+    failed: Option<String>,
+}
+#[derive(Debug)]
+pub struct FileSink {
+    dir: Option<PathBuf>,
+    quota: u64,     // largest file accepted
+    current: Option<Incoming>,
+}
+impl FileSink {
+    /*-- with no directory every transfer is refused --*/
+    pub fn new(dir: Option<PathBuf>) -> FileSink {
+        FileSink { dir, quota: DEFAULT_SINK_QUOTA, current: None }
+    }
+    /*-- refuse files larger than quota bytes --*/
+    pub fn with_quota(dir: Option<PathBuf>, quota: u64) -> FileSink {
+        FileSink { dir, quota, current: None }
+    }
+    /*-- returns reply to send, if msg needs one --*/
+    pub fn handle<M: Msg>(&mut self, msg: &M) -> Option<M> {
+        let msg_type = msg.get_type();
+        let reply = if msg_type == MessageType::FILE_BEGIN as u8 {
+            match self.begin(msg) {
+                Ok(offset) => FileReply::ok(offset),
+                Err(e) => FileReply::failed(&e.to_string()),
+            }
+        }
+        else if msg_type == MessageType::FILE_END as u8 {
+            match self.end(msg) {
+                Ok(size) => FileReply::ok(size),
+                Err(e) => FileReply::failed(&e.to_string()),
+            }
+        }
+        else {
+            if msg_type == MessageType::FILE_CHUNK as u8 {
+                self.chunk(msg);
+            }
+            return None;
+        };
+        let mut reply: M = reply.to_msg(msg_type);
+        reply.set_request_id(msg.get_request_id());
+        Some(reply)
+    }
+    fn begin<M: Msg>(&mut self, msg: &M) -> Result<u64> {
+        self.current = None;
+        let dir = self.dir.as_ref()
+            .ok_or_else(|| Error::other("listener has no sink directory"))?;
+        let begin = FileBegin::from_msg(msg)?;
+        if begin.size > self.quota {
+            return Err(Error::other(format!(
+                "file of {} bytes exceeds sink quota of {}", begin.size, self.quota
+            )));
+        }
+        /*-- only the final name component, never a path --*/
+        let name = Path::new(&begin.name).file_name()
+            .ok_or_else(|| invalid("FILE_BEGIN has no file name"))?;
+        let dest = dir.join(name);
+        let mut part = dest.clone().into_os_string();
+        part.push(".part");
+        let part = PathBuf::from(part);
+
+        let file = OpenOptions::new().create(true).truncate(false).write(true).read(true).open(&part)?;
+        let have = file.metadata()?.len();
+        let offset = begin.offset.min(have).min(begin.size);
+        file.set_len(offset)?;
+        self.current = Some(Incoming {
+            file, part, dest, size: begin.size, next: offset, failed: None,
+        });
+        Ok(offset)
+    }
+    fn chunk<M: Msg>(&mut self, msg: &M) {
+        let incoming = match self.current.as_mut() {
+            Some(incoming) => incoming,
+            None => return,
+        };
+        if incoming.failed.is_some() {
+            return;
+        }
+        let body = msg.get_content_bytes();
+        let rslt = match u64_at(body, 0) {
+            None => Err(invalid("FILE_CHUNK too short")),
+            Some(offset) if offset != incoming.next => Err(invalid("FILE_CHUNK out of order")),
+            Some(offset) => {
+                let data = &body[FILE_CHUNK_HEADER_SIZE..];
+                if offset + data.len() as u64 > incoming.size {
+                    Err(invalid("FILE_CHUNK past announced size"))
+                }
+                else {
+                    incoming.file.seek(SeekFrom::Start(offset))
+                        .and_then(|_| incoming.file.write_all(data))
+                        .map(|_| data.len() as u64)
+                }
+            }
+        };
+        match rslt {
+            Ok(len) => incoming.next += len,
+            Err(e) => incoming.failed = Some(e.to_string()),
+        }
+    }
+    fn end<M: Msg>(&mut self, msg: &M) -> Result<u64> {
+        let mut incoming = self.current.take()
+            .ok_or_else(|| invalid("FILE_END without FILE_BEGIN"))?;
+        let rslt = Self::verify(&mut incoming, msg.get_content_bytes());
+        if rslt.is_ok() {
+            fs::rename(&incoming.part, &incoming.dest)?;
+        }
+        else {
+            /*-- content can't be trusted, next attempt starts over --*/
+            let _ = fs::remove_file(&incoming.part);
+        }
+        rslt
+    }
+    fn verify(incoming: &mut Incoming, digest: &[u8]) -> Result<u64> {
+        if let Some(failed) = incoming.failed.take() {
+            return Err(Error::other(failed));
+        }
+        incoming.file.flush()?;
+        if incoming.next != incoming.size {
+            return Err(invalid(&format!(
+                "received {} of {} bytes", incoming.next, incoming.size
+            )));
+        }
+        let hasher = hash_prefix(&mut incoming.file, incoming.size)?;
+        if hasher.finalize().as_slice() != digest {
+            return Err(invalid("sha256 mismatch"));
+        }
+        Ok(incoming.size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn begin_round_trip() {
+        let begin = FileBegin { size: 1234, offset: 56, name: "data.json".to_string() };
+        let msg: Message = begin.to_msg();
+        assert_eq!(msg.get_type(), MessageType::FILE_BEGIN as u8);
+        assert_eq!(FileBegin::from_msg(&msg).unwrap(), begin);
+    }
+    #[test]
+    fn reply_round_trip() {
+        let reply = FileReply::failed("disk full");
+        let msg: Message = reply.to_msg(MessageType::FILE_END as u8);
+        let back = FileReply::from_msg(&msg).unwrap();
+        assert_eq!(back, reply);
+        assert!(back.into_result().is_err());
+    }
+    #[test]
+    fn sink_without_dir_refuses() {
+        let mut sink = FileSink::new(None);
+        let begin = FileBegin { size: 1, offset: 0, name: "x".to_string() };
+        let reply: Message = sink.handle(&begin.to_msg::<Message>()).unwrap();
+        assert!(!FileReply::from_msg(&reply).unwrap().ok);
+    }
+    #[test]
+    fn sink_refuses_files_over_quota() {
+        let dir = std::env::temp_dir().join(format!("rust_comm_quota_{}", std::process::id()));
+        let mut sink = FileSink::with_quota(Some(dir), 1000);
+        let begin = FileBegin { size: 1001, offset: 0, name: "big".to_string() };
+        let mut msg = begin.to_msg::<Message>();
+        msg.set_request_id(42);
+        let reply: Message = sink.handle(&msg).unwrap();
+        assert_eq!(reply.get_request_id(), 42);
+        let reply = FileReply::from_msg(&reply).unwrap();
+        assert!(!reply.ok);
+        assert!(reply.detail.contains("quota"));
+    }
+    #[test]
+    fn sink_fails_transfer_on_short_chunk() {
+        let dir = std::env::temp_dir().join(format!("rust_comm_short_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut sink = FileSink::new(Some(dir.clone()));
+        let begin = FileBegin { size: 10, offset: 0, name: "short".to_string() };
+        let reply: Message = sink.handle(&begin.to_msg::<Message>()).unwrap();
+        assert!(FileReply::from_msg(&reply).unwrap().ok);
+        let chunk: Message = build_msg(MessageType::FILE_CHUNK as u8, vec![0u8; 3]);
+        assert!(sink.handle(&chunk).is_none());
+        let reply: Message = sink.handle(&file_end::<Message>(&[0u8; SHA256_SIZE])).unwrap();
+        let reply = FileReply::from_msg(&reply).unwrap();
+        assert!(!reply.ok);
+        assert!(reply.detail.contains("too short"));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
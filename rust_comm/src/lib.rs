@@ -15,6 +15,37 @@
      - M is a message type
    P processes messages and its code must work with that
    of the Message type.
+
+   Every connection starts with a Hello exchange, defined in
+   rust_comm_processing.  Connector::new fails, and the
+   listener drops the session, if the two ends can't agree.
+
+   Bodies larger than CommOptions::fragment_size are sent as
+   fragments, taking turns with other queued messages, and
+   reassembled by the receiver before anyone sees them.
+
+   Messages are sent in priority order, see send_queue.rs,
+   by the Connector and by each listener session's writer.
+
+   Connector::send_file streams a file to the listener's sink
+   directory, see file_transfer.rs.
+
+   Messages past their deadline, see Msg::set_ttl, are dropped
+   by the Connector's send thread and by the listener instead
+   of being sent or processed.  With CommOptions::notify_expired
+   the Connector keeps the ones it drops for get_expired, and
+   the listener answers with an EXPIRED message carrying the
+   original body.  CommOptions::clock supplies the time.
+
+   Connector::post_request tags a message with a request id
+   that Connector::cancel can name in a CANCEL message.  Each
+   listener session processes requests on a worker thread,
+   handing P::process_cancellable a CancelToken that CANCEL
+   trips, and answers a cancelled request with CANCELLED.
+   Connector::request waits, up to a timeout, for the answer,
+   returning ERROR, CANCELLED, and EXPIRED replies as errors.
+   Replies reach the request call waiting for them by id, see
+   reply_slots.rs.
    
    Traits used by these types are defined in rust_traits.
 */
@@ -22,6 +53,15 @@
 #![allow(unused_imports)]
 #![allow(dead_code)]
 
+mod file_transfer;
+pub use file_transfer::*;
+mod send_queue;
+pub use send_queue::*;
+mod clock;
+pub use clock::*;
+mod reply_slots;
+use reply_slots::ReplySlots;
+
 /*-- rust_comm facilities --*/
 use rust_traits::*;
 use rust_message::*;
@@ -31,17 +71,46 @@ use rust_comm_logger::*;
 
 /*-- std library facilities --*/
 use std::fmt::*;
-use std::sync::{Arc, atomic::AtomicBool, atomic::Ordering};
+use std::sync::{Arc, Mutex, atomic::AtomicBool, atomic::AtomicU64, atomic::Ordering};
 use std::net::{TcpStream, TcpListener, Shutdown};
 use std::io::{Result, BufReader, BufWriter, stdout, Write};
 use std::io::prelude::*;
 use std::thread;
 use std::thread::{JoinHandle};
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 type L = MuteLog;
 type M = Message;
 type P = CommProcessing<L>;
 
+/*---------------------------------------------------------
+  CommOptions - endpoint settings fixed when a link opens
+*/
+#[derive(Debug, Clone)]
+pub struct CommOptions {
+    pub hello: Hello,
+    pub fragment_size: usize,   // largest body sent in one frame
+    pub reassembly_cap: usize,  // partial message bytes held per link
+    pub sink_dir: Option<PathBuf>,  // where listener stores received files
+    pub sink_quota: u64,        // largest file listener accepts
+    pub clock: Arc<dyn Clock>,  // checks message deadlines
+    pub notify_expired: bool,   // report expired messages instead of just dropping
+}
+impl Default for CommOptions {
+    fn default() -> CommOptions {
+        CommOptions {
+            hello: Hello::default(),
+            fragment_size: DEFAULT_FRAGMENT_SIZE,
+            reassembly_cap: DEFAULT_REASSEMBLY_CAP,
+            sink_dir: None,
+            sink_quota: DEFAULT_SINK_QUOTA,
+            clock: Arc::new(SystemClock),
+            notify_expired: false,
+        }
+    }
+}
 /*---------------------------------------------------------
   Connector<P,M,L> - attempts to connect to Listener<P,L>
 */
@@ -51,11 +120,17 @@ pub struct Connector<P,M,L> where
     P: Debug + Copy + Clone + Send + Sync + Default + Sndr<M> + Rcvr<M>, 
     L: Logger + Debug + Copy + Clone + Default
 {
-    snd_queue: Arc<BlockingQueue<M>>,
+    snd_queue: Arc<SendQueue<M>>,
     rcv_queue: Arc<BlockingQueue<M>>,
+    exp_queue: Arc<BlockingQueue<M>>,
+    waiting: Arc<ReplySlots<M>>,
+    next_request: AtomicU64,
+    sending_file: Mutex<()>,    // sink holds one transfer at a time
      _p: P,
      connected: bool,
      log: L,
+     negotiated: Negotiated,
+     fragment_size: usize,   // largest body sent unfragmented
     //  msg_size: usize,
 }
 impl<P,M,L> Connector<P,M,L> where
@@ -69,73 +144,208 @@ impl<P,M,L> Connector<P,M,L> where
     pub fn post_message(&self, msg: M) {
         self.snd_queue.en_q(msg);
     }
-    pub fn get_message(&self) -> M {
+    /*-- posts msg tagged with a new request id, returned for cancel --*/
+    pub fn post_request(&self, mut msg: M) -> u64 {
+        let id = self.next_request.fetch_add(1, Ordering::Relaxed);
+        msg.set_request_id(id);
+        self.post_message(msg);
+        id
+    }
+    /*-- listener answers request id with CANCELLED unless already done --*/
+    pub fn cancel(&self, request_id: u64) {
+        let mut msg = M::new(HEADER_SIZE);
+        msg.set_type(MessageType::CANCEL as u8);
+        msg.set_priority(PRIORITY_CONTROL);
+        msg.set_request_id(request_id);
+        self.post_message(msg);
+    }
+    /*---------------------------------------------------
+      Posts msg as a request and blocks, at most timeout,
+      for its reply.
+      - ERROR reply: Err carrying ErrorReply, see error_reply
+      - CANCELLED: Err with ErrorKind::Interrupted
+      - EXPIRED, from the listener or because this Connector
+        dropped the request at its deadline: Err with
+        ErrorKind::TimedOut
+      - no reply within timeout: the request is cancelled,
+        Err with ErrorKind::TimedOut, and a late reply is
+        left for get_message
+      - connection lost: Err with ErrorKind::ConnectionAborted
+      Other messages arriving meanwhile are left for
+      get_message, in the order they arrived.
+    */
+    pub fn request(&self, mut msg: M, timeout: Duration) -> std::io::Result<M> {
+        let id = self.next_request.fetch_add(1, Ordering::Relaxed);
+        msg.set_request_id(id);
+        /*-- slot opened first, the reply can't beat it --*/
+        let slot = match self.waiting.open(id) {
+            Some(slot) => slot,
+            None => return Err(std::io::ErrorKind::ConnectionAborted.into()),
+        };
+        self.post_message(msg);
+        let reply = match slot.de_q_timeout(timeout) {
+            Some(reply) => reply,
+            None if slot.is_closed() => {
+                return Err(std::io::ErrorKind::ConnectionAborted.into());
+            }
+            None => {
+                self.waiting.forget(id);
+                self.cancel(id);
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut, format!("request {} timed out", id)
+                ));
+            }
+        };
+        let msg_type = reply.get_type();
+        if msg_type == MessageType::ERROR as u8 {
+            let err = match ErrorReply::from_msg(&reply) {
+                Some(err) => std::io::Error::other(err),
+                None => std::io::Error::new(
+                    std::io::ErrorKind::InvalidData, "malformed ERROR reply"
+                ),
+            };
+            return Err(err);
+        }
+        if msg_type == MessageType::CANCELLED as u8 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Interrupted, format!("request {} cancelled", id)
+            ));
+        }
+        if msg_type == MessageType::EXPIRED as u8 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut, format!("request {} expired", id)
+            ));
+        }
+        Ok(reply)
+    }
+    /*-- blocks for next msg, None once connection is lost and all are read --*/
+    pub fn get_message(&self) -> Option<M> {
         self.rcv_queue.de_q()
     }
     pub fn has_msg(&self) -> bool {
-        self.rcv_queue.len() > 0
+        !self.rcv_queue.is_empty()
+    }
+    /*-- next msg dropped unsent at its deadline, needs notify_expired --*/
+    /*-- None once the send thread has stopped and all are read --*/
+    pub fn get_expired(&self) -> Option<M> {
+        self.exp_queue.de_q()
     }
-    pub fn new(addr: &'static str) -> std::io::Result<Connector<P,M,L>>
-    where
-        M: Msg + Clone + Send + Default + 'static,
-        P: Debug + Copy + Clone + Send + Sync + Default + Sndr<M> + Rcvr<M>,
-        L: Logger + Copy + Clone + Default
+    pub fn has_expired(&self) -> bool {
+        !self.exp_queue.is_empty()
+    }
+    /*-- settings agreed with the listener during Hello exchange --*/
+    pub fn negotiated(&self) -> &Negotiated {
+        &self.negotiated
+    }
+    /*-- sends file to listener's sink dir, resuming a partial copy --*/
+    pub fn send_file(&self, path: &Path) -> std::io::Result<u64> {
+        self.send_file_from(path, u64::MAX)
+    }
+    /*-- starts no later than offset, returns bytes sent --*/
+    /*-- concurrent calls wait their turn, sink takes one file at a time --*/
+    pub fn send_file_from(&self, path: &Path, offset: u64) -> std::io::Result<u64> {
+        let _turn = self.sending_file.lock().unwrap_or_else(|e| e.into_inner());
+        file_transfer::send_file(
+            &self.snd_queue, |msg| self.request(msg, FILE_REPLY_TIMEOUT),
+            path, offset, self.fragment_size
+        )
+    }
+    pub fn new(addr: &'static str) -> std::io::Result<Connector<P,M,L>> {
+        Self::with_hello(addr, Hello::default())
+    }
+    /*-- connect announcing hello, e.g., with an application name --*/
+    pub fn with_hello(addr: &'static str, hello: Hello) 
+        -> std::io::Result<Connector<P,M,L>>
     {
-        let mut _is_connected = false;
-        let rslt = TcpStream::connect(addr);
-        if rslt.is_err() {
-             print!("\n-- connection to {:?} failed --", addr);
-             return Err(std::io::Error::new(std::io::ErrorKind::Other, "connect failed"));
-        }
-        else {
-            _is_connected = true;
-            L::write(&format!("\n--connected to {:?}--", addr));
-        }
-        let stream = rslt.unwrap();
+        Self::with_options(addr, CommOptions { hello, ..CommOptions::default() })
+    }
+    pub fn with_options(addr: &'static str, options: CommOptions) 
+        -> std::io::Result<Connector<P,M,L>>
+    {
+        let stream = match TcpStream::connect(addr) {
+            Ok(stream) => stream,
+            Err(_) => {
+                print!("\n-- connection to {:?} failed --", addr);
+                return Err(std::io::Error::other("connect failed"));
+            }
+        };
+        L::write(&format!("\n--connected to {:?}--", addr));
         let mut buf_writer = BufWriter::new(stream.try_clone()?);
         let mut buf_reader = BufReader::new(stream.try_clone()?);
+
+        /*-- refuse to exchange messages with an incompatible peer --*/
+        stream.set_read_timeout(Some(HELLO_TIMEOUT))?;
+        let negotiated = client_handshake(&options.hello, &mut buf_reader, &mut buf_writer)?;
+        stream.set_read_timeout(None)?;
+        L::write(&format!("\n--handshake with {:?} complete--", negotiated.peer_name));
         
-        let send_queue = Arc::new(BlockingQueue::<M>::new());
+        let send_queue = Arc::new(SendQueue::<M>::new());
         let recv_queue = Arc::new(BlockingQueue::<M>::new());
+        let expired_queue = Arc::new(BlockingQueue::<M>::new());
+        let waiting = Arc::new(ReplySlots::<M>::new());
         
         /*-- send thread reads input queue and sends msg --*/
         let sqm = Arc::clone(&send_queue);
+        let eqm = Arc::clone(&expired_queue);
+        let expiry = Expiry {
+            clock: Arc::clone(&options.clock),
+            notify: if options.notify_expired { Some(Arc::clone(&expired_queue)) } else { None },
+            waiting: Arc::clone(&waiting),
+        };
+        let link = negotiated.clone();
+        let fragment_size = fragment_size_for(
+            options.fragment_size, negotiated.max_frame_size
+        );
         let _ = std::thread::spawn(move || {
             let ssq = Arc::clone(&sqm);
-            loop {
-                L::write("\n  -- dequing send msg --");
-                let msg = ssq.de_q();
-                L::write("\n  sending msg");
-                let msg_type = msg.get_type();
-                let rslt = P::buf_send_message(&msg, &mut buf_writer);
-                if rslt.is_err() {
-                    // may cause panic if io doesn't complete before 
-                    // thread shuts down
-                    // print!("\n  msg send error");
-                    break;
-                }
-                L::write("\n  -- send successful --");
-                if msg_type == MessageType::END as u8 {
-                    L::write("\n--terminating connector send thread--");
-                    break;
-                }
-            }            
+            let mut outbox = Outbox::<P,M,L>::new(link, fragment_size);
+            outbox.set_expiry(expiry);
+            let _ = send_loop(&ssq, &mut outbox, &mut buf_writer);
+            /*-- nothing more will expire here --*/
+            eqm.close();
         });
         /*-- recv thread recvs msg (may block) and enQs for user --*/
         let rqm = Arc::clone(&recv_queue);
+        let sqr = Arc::clone(&send_queue);
+        let wtg = Arc::clone(&waiting);
+        let max_frame = negotiated.max_frame_size;
+        let mut reassembler = Reassembler::<M>::new(options.reassembly_cap);
         let _ = std::thread::spawn(move || {
             let srq = Arc::clone(&rqm);
             loop {
                 L::write("\n  attempting to receive msg in connector");
-                let rslt = P::buf_recv_message(&mut buf_reader);
-                if rslt.is_err() {
-                    L::write("\n--terminating connector receive thread--");
-                    break;
+                let rslt = P::buf_recv_message_limited(&mut buf_reader, max_frame);
+                let msg = match rslt {
+                    Ok(msg) => msg,
+                    Err(e) => {
+                        /*-- corrupt stream, don't leave send side writing into it --*/
+                        if is_checksum_mismatch(&e) {
+                            print!("\n  {}, closing connection", e);
+                            let _ = buf_reader.get_ref().shutdown(Shutdown::Both);
+                        }
+                        L::write("\n--terminating connector receive thread--");
+                        break;
+                    }
+                };
+                match reassembler.accept(msg) {
+                    Ok(Some(msg)) => {
+                        if let Some(msg) = wtg.deliver(msg) {
+                            let _ = srq.en_q(msg);
+                        }
+                    }
+                    Ok(None) => continue,
+                    Err(e) => {
+                        print!("\n  {}, message dropped", e);
+                        continue;
+                    }
                 }
-                let msg = rslt.unwrap();
-                srq.en_q(msg);
                 L::write(&format!("\n  recv_queue len: {}", srq.len()));
             }
+            /*-- nothing more will arrive, don't leave readers waiting --*/
+            srq.close();
+            wtg.close();
+            /*-- send thread can't deliver anything either, let it finish --*/
+            sqr.close();
         });
         /*-- return new Connector as std::io::Result --*/
         let me =
@@ -143,27 +353,256 @@ impl<P,M,L> Connector<P,M,L> where
             _p: P::default(),
             snd_queue: send_queue,
             rcv_queue: recv_queue,
-            connected: _is_connected,
+            exp_queue: expired_queue,
+            waiting,
+            next_request: AtomicU64::new(1),
+            sending_file: Mutex::new(()),
+            connected: true,
             log: L::default(),
+            negotiated,
+            fragment_size,
             // msg_size: msg_size,
         };
         Ok(me)
     }
 }
+/*---------------------------------------------------------
+  Send thread, for a Connector or a listener session
+  - drains every ready message, up to SEND_BATCH, and
+    writes them with one vectored write
+  - queued messages take turns with fragments of large
+    messages already in progress, unless the queue holds
+    only lower priority messages
+  - fragments still in progress go out before END or QUIT
+  - flushes whenever the queue is empty, so callers don't
+    need FLUSH messages for latency
+  - returns after sending END, or when ssq is closed
+*/
+pub const SEND_BATCH: usize = 64;
+
+fn send_loop<P,M,L>(
+    ssq: &SendQueue<M>, 
+    outbox: &mut Outbox<P,M,L>, 
+    writer: &mut BufWriter<TcpStream>
+) -> Result<()> 
+where
+    M: Msg + Clone + Send + Default,
+    P: Sndr<M>,
+    L: Logger,
+{
+    loop {
+        if outbox.is_idle() || queue_goes_first(ssq, outbox) {
+            L::write("\n  -- dequing send msgs --");
+            let mut msg = match ssq.de_q() {
+                Some(msg) => msg,
+                None => {
+                    outbox.finish(writer)?;
+                    return writer.flush();
+                }
+            };
+            loop {
+                let msg_type = msg.get_type();
+                if msg_type == MessageType::END as u8 
+                    || msg_type == MessageType::QUIT as u8 
+                {
+                    outbox.finish(writer)?;
+                }
+                outbox.post(msg);
+                if msg_type == MessageType::END as u8 {
+                    outbox.write_ready(writer)?;
+                    writer.flush()?;
+                    L::write("\n--terminating send thread--");
+                    return Ok(());
+                }
+                if outbox.ready_len() >= SEND_BATCH || !queue_goes_first(ssq, outbox) {
+                    break;
+                }
+                msg = match ssq.de_q() {
+                    Some(msg) => msg,
+                    None => break,
+                };
+            }
+        }
+        outbox.send_fragment();
+        L::write("\n  sending batch");
+        outbox.write_ready(writer)?;
+        if ssq.is_empty() {
+            writer.flush()?;
+        }
+    }
+}
+/*-- next queued msg outranks fragments in progress --*/
+fn queue_goes_first<P,M,L>(ssq: &SendQueue<M>, outbox: &Outbox<P,M,L>) -> bool 
+where
+    M: Msg + Clone + Send + Default,
+    P: Sndr<M>,
+    L: Logger,
+{
+    match (ssq.peek_priority(), outbox.top_priority()) {
+        (None, _) => false,
+        (Some(_), None) => true,
+        (Some(queued), Some(active)) => queued >= active,
+    }
+}
+/*---------------------------------------------------------
+  Expiry<M> - how an Outbox treats messages past deadline
+*/
+struct Expiry<M> {
+    clock: Arc<dyn Clock>,
+    notify: Option<Arc<BlockingQueue<M>>>,  // gets dropped messages
+    waiting: Arc<ReplySlots<M>>,            // answers dropped requests EXPIRED
+}
+/*---------------------------------------------------------
+  Outbox<P,M,L> - sending side of one link
+  - applies negotiated features to each frame
+  - drops expired messages, if given an Expiry
+  - holds large messages while their fragments are sent
+  - collects frames until write_ready sends them as a batch
+*/
+struct Outbox<P,M,L> where M: Msg {
+    link: Negotiated,
+    fragment_size: usize,
+    expiry: Option<Expiry<M>>,
+    active: VecDeque<Fragmenter<M>>,
+    ready: Vec<M>,
+    next_id: u64,
+    _p: std::marker::PhantomData<fn() -> (P,L)>,
+}
+impl<P,M,L> Outbox<P,M,L> where 
+    M: Msg + Clone + Send + Default,
+    P: Sndr<M>,
+    L: Logger,
+{
+    fn new(link: Negotiated, fragment_size: usize) -> Outbox<P,M,L> {
+        let fragment_size = fragment_size_for(fragment_size, link.max_frame_size);
+        Outbox {
+            link,
+            fragment_size,
+            expiry: None,
+            active: VecDeque::new(),
+            ready: Vec::new(),
+            next_id: 0,
+            _p: std::marker::PhantomData,
+        }
+    }
+    /*-- without one, messages are sent whatever their deadline --*/
+    fn set_expiry(&mut self, expiry: Expiry<M>) {
+        self.expiry = Some(expiry);
+    }
+    /*-- no fragments waiting to be sent --*/
+    fn is_idle(&self) -> bool {
+        self.active.is_empty()
+    }
+    /*-- highest priority among messages being fragmented --*/
+    fn top_priority(&self) -> Option<u8> {
+        self.active.iter().map(|frags| frags.priority()).max()
+    }
+    /*-- frames waiting for write_ready --*/
+    fn ready_len(&self) -> usize {
+        self.ready.len()
+    }
+    /*-- queue msg, or first fragment of it if too large --*/
+    fn post(&mut self, msg: M) {
+        if let Some(expiry) = &self.expiry {
+            if msg.is_expired(expiry.clock.now_millis()) {
+                L::write("\n  dropping expired message");
+                let msg = expire_request(&expiry.waiting, msg);
+                if let (Some(notify), Some(msg)) = (&expiry.notify, msg) {
+                    let _ = notify.en_q(msg);
+                }
+                return;
+            }
+        }
+        if !needs_fragmenting(&msg, self.fragment_size) {
+            self.push_frame(msg);
+            return;
+        }
+        self.next_id += 1;
+        L::write(&format!("\n  fragmenting message {}", self.next_id));
+        self.active.push_back(Fragmenter::new(msg, self.next_id, self.fragment_size));
+        self.send_fragment();
+    }
+    /*-- one fragment from the oldest top priority message, round robin --*/
+    fn send_fragment(&mut self) {
+        let top = match self.top_priority() {
+            Some(top) => top,
+            None => return,
+        };
+        let pos = self.active.iter().position(|frags| frags.priority() == top);
+        let mut frags = match pos.and_then(|pos| self.active.remove(pos)) {
+            Some(frags) => frags,
+            None => return,
+        };
+        if let Some(frag) = frags.next() {
+            self.push_frame(frag);
+        }
+        if !frags.is_done() {
+            self.active.push_back(frags);
+        }
+    }
+    /*-- writes everything queued, including all remaining fragments --*/
+    fn finish(&mut self, writer: &mut BufWriter<TcpStream>) -> Result<()> {
+        self.write_ready(writer)?;
+        while !self.is_idle() {
+            self.send_fragment();
+            self.write_ready(writer)?;
+        }
+        Ok(())
+    }
+    fn write_ready(&mut self, writer: &mut BufWriter<TcpStream>) -> Result<()> {
+        if self.ready.is_empty() {
+            return Ok(());
+        }
+        let rslt = P::buf_send_batch(&self.ready, writer);
+        self.ready.clear();
+        rslt
+    }
+    fn push_frame(&mut self, mut msg: M) {
+        apply_features(&mut msg, &self.link);
+        self.ready.push(msg);
+    }
+}
+/*-- answers a request call waiting for msg, else returns msg --*/
+fn expire_request<M: Msg + Clone>(waiting: &ReplySlots<M>, msg: M) -> Option<M> {
+    let mut reply = msg.clone();
+    reply.set_type(MessageType::EXPIRED as u8);
+    waiting.deliver(reply).map(|_| msg)
+}
+/*---------------------------------------------------------
+  Mark outgoing msg for features negotiated on its link
+*/
+fn apply_features<M: Msg>(msg: &mut M, link: &Negotiated) {
+    if link.has_feature(FEATURE_COMPRESSION) {
+        compress_message(msg);
+    }
+    if link.has_feature(FEATURE_CHECKSUM) {
+        msg.set_flags(msg.get_flags() | FLAG_CHECKSUM);
+    }
+}
 /*---------------------------------------------------------
   Each threadpool thread executes thread_proc
   - get next TcpStream instance, strm
   - communicate with connecter using handle_client(strm)
+  - while idle, checks run every IDLE_POLL
+  - returns when bq is closed and drained
 */
-pub fn thread_proc(bq: &BlockingQueue<TcpStream>, run: &Arc<AtomicBool>) {
+pub const IDLE_POLL: std::time::Duration = std::time::Duration::from_millis(100);
+
+pub fn thread_proc(
+    bq: &BlockingQueue<TcpStream>, run: &Arc<AtomicBool>, options: &CommOptions
+) {
     loop {
         if !run.load(Ordering::Relaxed) {
             print!("\n  terminating listener thread");
             // let _ = std::io::stdout().flush();
             break;
         }
-        let strm = bq.de_q();
-        let rslt = handle_client(strm);
+        let strm = match bq.de_q_timeout(IDLE_POLL) {
+            Some(strm) => strm,
+            None if bq.is_closed() => break,
+            None => continue,
+        };
+        let rslt = handle_client::<P,L>(strm, options);
         if rslt.is_err() {
             print!("\n  stream failure in handle_client");
             break;  // this kills one threadpool thread
@@ -172,26 +611,82 @@ pub fn thread_proc(bq: &BlockingQueue<TcpStream>, run: &Arc<AtomicBool>) {
 }
 /*---------------------------------------------------------
   Handle client messages:
-  - extract message, msg, from stream 
-  - process using reply_msg = P::process_message(msg)
-  - send back reply_msg
+  - answer connector's Hello, give up if incompatible
+  - extract message, msg, from stream, rejoining fragments
+  - drop msg if past its deadline, answering EXPIRED if
+    options.notify_expired
+  - file transfer messages go to the session's FileSink
+  - CANCEL trips the token of the request it names, even
+    one that hasn't arrived yet
+  - other messages go to the session's worker thread, see
+    process_loop, so CANCEL is read while they run
+  - EXPIRED and file transfer replies go through the worker
+    too, so every reply leaves in the order its message came
+  - replies are posted to the session's writer thread, which
+    sends them in priority order
 */
-pub fn handle_client(strm: TcpStream) -> Result<()> {
+pub fn handle_client<P,L>(strm: TcpStream, options: &CommOptions) -> Result<()> 
+where
+    P: Sndr<M> + Rcvr<M> + Process<M> + 'static,
+    L: Logger + 'static,
+{
 
     /*-- thread handles client until receiving an END or QUIT message --*/
     let mut buf_writer = BufWriter::new(strm.try_clone()?);
     let mut buf_reader = BufReader::new(strm.try_clone()?);
+    /*-- a connector that never says Hello can't hold the session --*/
+    strm.set_read_timeout(Some(HELLO_TIMEOUT))?;
+    let link = match server_handshake(&options.hello, &mut buf_reader, &mut buf_writer) {
+        Ok(link) => link,
+        /*-- peer's fault, not the stream's, the thread serves the next client --*/
+        Err(e) => {
+            print!("\n  handshake failed: {}", e);
+            return Ok(());
+        }
+    };
+    strm.set_read_timeout(None)?;
+    let max_frame = link.max_frame_size;
+    let replies = Arc::new(SendQueue::<M>::new());
+    let writer_queue = Arc::clone(&replies);
+    let fragment_size = options.fragment_size;
+    let writer = std::thread::spawn(move || {
+        let mut outbox = Outbox::<P,M,L>::new(link, fragment_size);
+        send_loop(&writer_queue, &mut outbox, &mut buf_writer)
+    });
+    let tokens = Arc::new(Mutex::new(HashMap::<u64, CancelToken>::new()));
+    let work = Arc::new(BlockingQueue::<Work>::new());
+    let worker = {
+        let (work, replies, tokens) = (Arc::clone(&work), Arc::clone(&replies), Arc::clone(&tokens));
+        std::thread::spawn(move || process_loop::<P,L>(&work, &replies, &tokens))
+    };
+    let mut reassembler = Reassembler::<M>::new(options.reassembly_cap);
+    let mut sink = FileSink::with_quota(options.sink_dir.clone(), options.sink_quota);
     loop {
         L::write("\n  attempting to recv message in client handler");
         // let _ = std::io::stdout().flush();
-        let rslt:Result<Message> = P::buf_recv_message(&mut buf_reader);
+        let rslt:Result<Message> = P::buf_recv_message_limited(&mut buf_reader, max_frame);
         L::write("\n  receive successful in client handler");
-        if rslt.is_err() {
-            print!("\n  socket session closed abruptly");
-            // let _ = std::io::stdout().flush();
-            break;
-        }
-        let mut msg = rslt.unwrap();
+        let msg = match rslt {
+            Ok(msg) => msg,
+            Err(e) => {
+                if is_checksum_mismatch(&e) {
+                    print!("\n  {}, closing connection", e);
+                }
+                else {
+                    print!("\n  socket session closed abruptly");
+                }
+                // let _ = std::io::stdout().flush();
+                break;
+            }
+        };
+        let mut msg = match reassembler.accept(msg) {
+            Ok(Some(msg)) => msg,
+            Ok(None) => continue,
+            Err(e) => {
+                print!("\n  {}, message dropped", e);
+                continue;
+            }
+        };
         if msg.get_type() == MessageType::END as u8 {
             L::write("\n--listener received END message--");
             L::write("\n--terminating client handler loop--");           
@@ -202,12 +697,89 @@ pub fn handle_client(strm: TcpStream) -> Result<()> {
             L::write("\n--terminating client handler loop--");
             break;
         }
-        P::process_message(&mut msg);
-        let _ = P::buf_send_message(&msg, &mut buf_writer);
+        else if msg.is_expired(options.clock.now_millis()) {
+            L::write("\n  dropping expired message");
+            if options.notify_expired {
+                msg.set_type(MessageType::EXPIRED as u8);
+                let _ = work.en_q(Work::Answered(msg));
+            }
+        }
+        else if msg.get_type() == MessageType::CANCEL as u8 {
+            let id = msg.get_request_id();
+            if id != 0 {
+                L::write(&format!("\n  cancelling request {}", id));
+                tokens.lock().unwrap().entry(id).or_default().cancel();
+            }
+        }
+        else if is_file_type(msg.get_type()) {
+            if let Some(reply) = sink.handle(&msg) {
+                let _ = work.en_q(Work::Answered(reply));
+            }
+        }
+        else {
+            let token = match msg.get_request_id() {
+                0 => CancelToken::new(),
+                id => tokens.lock().unwrap().entry(id).or_default().clone(),
+            };
+            let _ = work.en_q(Work::Request(msg, token));
+        }
     } 
+    /*-- worker finishes queued requests, then writer sends replies and exits --*/
+    work.close();
+    let _ = worker.join();
+    replies.close();
+    let _ = writer.join();
     L::write("\n  terminating handler thread");
     Ok(())
 }
+/*-- session worker's queue, in order of arrival --*/
+enum Work {
+    Request(M, CancelToken),    // process, then reply
+    Answered(M),                // reply ready, send after earlier ones
+}
+/*---------------------------------------------------------
+  Session worker, processes requests in arrival order
+  - a request cancelled before it starts isn't processed
+  - P::process_cancellable may return early once its token
+    is cancelled, either way the reply is CANCELLED with an
+    empty body and the request's id
+  - returns when work is closed, as the session ends
+  Tokens of finished requests are forgotten.  A CANCEL for
+  one of them leaves a cancelled token behind, harmless as
+  Connectors never reuse request ids.
+*/
+fn process_loop<P,L>(
+    work: &BlockingQueue<Work>,
+    replies: &SendQueue<M>,
+    tokens: &Mutex<HashMap<u64, CancelToken>>,
+)
+where
+    P: Process<M>,
+    L: Logger,
+{
+    while let Some(item) = work.de_q() {
+        let (mut msg, token) = match item {
+            Work::Request(msg, token) => (msg, token),
+            Work::Answered(reply) => {
+                replies.en_q(reply);
+                continue;
+            }
+        };
+        if !token.is_cancelled() {
+            P::process_cancellable(&mut msg, &token);
+        }
+        let id = msg.get_request_id();
+        if id != 0 {
+            tokens.lock().unwrap().remove(&id);
+        }
+        if token.is_cancelled() {
+            L::write(&format!("\n  request {} cancelled", id));
+            msg.set_type(MessageType::CANCELLED as u8);
+            msg.set_body(Bytes::new());
+        }
+        replies.en_q(msg);
+    }
+}
 /*---------------------------------------------------------
   Listener<P,L> 
   - attempts to bind to listening address
@@ -217,18 +789,28 @@ pub fn handle_client(strm: TcpStream) -> Result<()> {
 pub struct Listener<P,L> 
 where 
 P: Debug + Copy + Clone + Send + Sync + Default + Sndr<M> + Rcvr<M> + 'static,
-L: Logger + Debug + Copy + Clone + Default
+L: Logger + Debug + Copy + Clone + Default + 'static
 {
     p: P,
     run: Arc<AtomicBool>,  // used to terminate Listener
     log: L, 
     addr: &'static str,
+    options: CommOptions,
     /*-- ThreadPool instance is aggregated in self.start() --*/
 }
+impl<P,L> Default for Listener<P,L> 
+where 
+    P: Debug + Copy + Clone + Send + Sync + Default + Sndr<M> + Rcvr<M> + Process<M> + 'static,
+    L: Logger + Debug + Copy + Clone + Default + 'static
+{
+    fn default() -> Listener<P,L> {
+        Listener::new()
+    }
+}
 impl<P,L> Listener<P,L> 
 where 
     P: Debug + Copy + Clone + Send + Sync + Default + Sndr<M> + Rcvr<M> + Process<M> + 'static,
-    L: Logger + Debug + Copy + Clone + Default
+    L: Logger + Debug + Copy + Clone + Default + 'static
     {    
     pub fn new() -> Listener<P,L> {
         Listener {
@@ -236,8 +818,21 @@ where
               run: Arc::new(AtomicBool::new(true)),
               log: L::default(),
               addr: "",
+              options: CommOptions::default(),
         }
     }
+    /*-- Hello sent to each connector, call before start --*/
+    pub fn set_hello(&mut self, hello: Hello) {
+        self.options.hello = hello;
+    }
+    /*-- settings used for each client session, call before start --*/
+    pub fn set_options(&mut self, options: CommOptions) {
+        self.options = options;
+    }
+    /*-- directory for files sent with Connector::send_file --*/
+    pub fn set_sink_dir(&mut self, dir: &Path) {
+        self.options.sink_dir = Some(dir.to_path_buf());
+    }
     /*-- starts thread wrapping incoming loop which often blocks --*/
     pub fn start(&mut self, addr: &'static str) -> Result<JoinHandle<()>> 
     {
@@ -246,10 +841,11 @@ where
         let rslt = TcpListener::bind(addr);
         if rslt.is_err() {
             print!("\n  binding to {:?} failed", addr);
-            return Err(std::io::Error::new(std::io::ErrorKind::Other, "listener bind failed"));
+            return Err(std::io::Error::other("listener bind failed"));
         }
         let tcpl = rslt.unwrap();
         let run_ref = Arc::clone(&self.run);
+        let options = self.options.clone();
 
         /*-- this outer thread prevents appl from blocking waiting for connections --*/
         let handle = std::thread::spawn(move || {
@@ -258,8 +854,8 @@ where
                 if !run_ref.load(Ordering::Relaxed) {
                     break;
                 }
-                if stream.is_ok() {
-                    let rslt = handle_client(stream.unwrap());
+                if let Ok(stream) = stream {
+                    let rslt = handle_client::<P,L>(stream, &options);
                     if rslt.is_err() {
                         continue;
                     }
@@ -275,17 +871,544 @@ where
     }
     pub fn stop(&mut self) {
         self.run.store(false, Ordering::Relaxed);
-        let conn = Connector::<P,M,L>::new(self.addr).unwrap();
-        let mut msg = Message::new(TYPE_SIZE + CONTENT_SIZE + 1);
-        msg.set_type(MessageType::QUIT as u8);
-        conn.post_message(msg);
+        /*-----------------------------------------------------
+          connecting unblocks accept, listener thread then
+          sees run == false and exits.  No Hello is sent, the
+          listener may be busy with a session, and connect
+          completes without waiting for it to accept.
+        */
+        let _ = TcpStream::connect(self.addr);
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use std::time::Duration;
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+    #[test]
+    fn idle_thread_proc_sees_run_flag() {
+        let bq = Arc::new(BlockingQueue::<TcpStream>::new());
+        let run = Arc::new(AtomicBool::new(true));
+        let worker = {
+            let (bq, run) = (Arc::clone(&bq), Arc::clone(&run));
+            thread::spawn(move || thread_proc(&bq, &run, &CommOptions::default()))
+        };
+        /*-- idle through at least one poll before run is cleared --*/
+        thread::sleep(2 * IDLE_POLL);
+        assert!(!worker.is_finished());
+        run.store(false, Ordering::Relaxed);
+        let start = std::time::Instant::now();
+        while !worker.is_finished() && start.elapsed() < 10 * IDLE_POLL {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert!(worker.is_finished(), "thread_proc still running after run cleared");
+        worker.join().unwrap();
+    }
+    #[test]
+    fn failed_handshake_keeps_thread_proc() {
+        let addr = "127.0.0.1:8107";
+        let tcpl = TcpListener::bind(addr).unwrap();
+        let bq = Arc::new(BlockingQueue::<TcpStream>::new());
+        let run = Arc::new(AtomicBool::new(true));
+        let worker = {
+            let (bq, run) = (Arc::clone(&bq), Arc::clone(&run));
+            thread::spawn(move || thread_proc(&bq, &run, &CommOptions::default()))
+        };
+        let mut bad = TcpStream::connect(addr).unwrap();
+        bad.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        bq.en_q(tcpl.accept().unwrap().0).unwrap();
+        thread::sleep(2 * IDLE_POLL);
+        assert!(!worker.is_finished(), "thread_proc exited after a bad Hello");
+
+        let good = thread::spawn(move || {
+            let conn = Connector::<P,M,L>::new(addr).unwrap();
+            let mut msg = Message::create_msg_header_only();
+            msg.set_type(MessageType::END as u8);
+            conn.post_message(msg);
+        });
+        bq.en_q(tcpl.accept().unwrap().0).unwrap();
+        good.join().unwrap();
+        run.store(false, Ordering::Relaxed);
+        bq.close();
+        worker.join().unwrap();
+    }
+    #[test]
+    fn handshake_agrees() {
+        let addr = "127.0.0.1:8091";
+        let mut lsnr = Listener::<P,L>::new();
+        lsnr.set_hello(Hello::new("test listener"));
+        let handle = lsnr.start(addr).unwrap();
+        let conn = Connector::<P,M,L>::with_hello(addr, Hello::new("test client")).unwrap();
+        assert_eq!(conn.negotiated().peer_name, "test listener");
+        let mut msg = Message::create_msg_str_fit("hello");
+        msg.set_type(MessageType::FLUSH as u8);
+        conn.post_message(msg);
+        let reply = conn.get_message().unwrap();
+        assert_eq!(reply.get_content_str().unwrap(), "hello");
+        let mut msg = Message::create_msg_header_only();
+        msg.set_type(MessageType::END as u8);
+        conn.post_message(msg);
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn header_flags_survive_round_trip() {
+        let addr = "127.0.0.1:8093";
+        let mut lsnr = Listener::<P,L>::new();
+        let handle = lsnr.start(addr).unwrap();
+        let conn = Connector::<P,M,L>::new(addr).unwrap();
+        let mut msg = Message::create_msg_str_fit("{\"x\":1}");
+        msg.set_type(MessageType::FLUSH as u8);
+        msg.set_flags(FLAG_JSON);
+        msg.set_header("content-type", "application/json").unwrap();
+        conn.post_message(msg);
+        let reply = conn.get_message().unwrap();
+        assert_eq!(reply.get_flags(), FLAG_JSON);
+        assert_eq!(reply.get_header("content-type"), Some("application/json"));
+        let mut msg = Message::create_msg_header_only();
+        msg.set_type(MessageType::END as u8);
+        conn.post_message(msg);
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn large_body_compressed_in_transit() {
+        let addr = "127.0.0.1:8094";
+        let mut lsnr = Listener::<P,L>::new();
+        let handle = lsnr.start(addr).unwrap();
+        let conn = Connector::<P,M,L>::new(addr).unwrap();
+        assert!(conn.negotiated().has_feature(FEATURE_COMPRESSION));
+        let body = vec![7u8; 64 * 1024];
+        let mut msg = Message::create_msg_bytes_fit(&body);
+        msg.set_type(MessageType::FLUSH as u8);
+        conn.post_message(msg);
+        let reply = conn.get_message().unwrap();
+        assert_eq!(reply.get_flags(), 0);
+        assert_eq!(reply.get_content_bytes(), &body[..]);
+        let mut msg = Message::create_msg_header_only();
+        msg.set_type(MessageType::END as u8);
+        conn.post_message(msg);
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn large_body_fragmented() {
+        let addr = "127.0.0.1:8095";
+        let options = CommOptions { fragment_size: 1024, ..CommOptions::default() };
+        let mut lsnr = Listener::<P,L>::new();
+        lsnr.set_options(options.clone());
+        let handle = lsnr.start(addr).unwrap();
+        let conn = Connector::<P,M,L>::with_options(addr, options).unwrap();
+        let body: Vec<u8> = (0..200_000).map(|i| (i * 7 % 251) as u8).collect();
+        let mut large = Message::create_msg_bytes_fit(&body);
+        large.set_type(MessageType::FLUSH as u8);
+        large.set_header("trace-id", "4bf92f35").unwrap();
+        conn.post_message(large);
+        let mut small = Message::create_msg_str_fit("small");
+        small.set_type(MessageType::FLUSH as u8);
+        conn.post_message(small);
+        let mut replies = [conn.get_message().unwrap(), conn.get_message().unwrap()];
+        replies.sort_by_key(|m| m.get_content_size());
+        assert_eq!(replies[0].get_content_str().unwrap(), "small");
+        assert_eq!(replies[1].get_type(), MessageType::FLUSH as u8);
+        assert_eq!(replies[1].get_content_bytes(), &body[..]);
+        assert_eq!(replies[1].get_header("trace-id"), Some("4bf92f35"));
+        let mut msg = Message::create_msg_header_only();
+        msg.set_type(MessageType::END as u8);
+        conn.post_message(msg);
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn handshake_version_mismatch() {
+        let addr = "127.0.0.1:8092";
+        let mut lsnr = Listener::<P,L>::new();
+        let handle = lsnr.start(addr).unwrap();
+        let mut hello = Hello::new("old client");
+        hello.version += 1;
+        let err = Connector::<P,M,L>::with_hello(addr, hello).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("protocol version mismatch"));
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn stop_returns_while_session_is_open() {
+        let addr = "127.0.0.1:8103";
+        let mut lsnr = Listener::<P,L>::new();
+        let handle = lsnr.start(addr).unwrap();
+        let conn = Connector::<P,M,L>::new(addr).unwrap();
+        let start = std::time::Instant::now();
+        lsnr.stop();
+        assert!(start.elapsed() < Duration::from_secs(1));
+        let mut msg = Message::create_msg_header_only();
+        msg.set_type(MessageType::END as u8);
+        conn.post_message(msg);
+        let _ = handle.join();
+    }
+    #[test]
+    fn replies_arrive_without_flush_messages() {
+        let addr = "127.0.0.1:8098";
+        let mut lsnr = Listener::<P,L>::new();
+        let handle = lsnr.start(addr).unwrap();
+        let conn = Connector::<P,M,L>::new(addr).unwrap();
+        /*-- one at a time, each reply must come back before END is sent --*/
+        for i in 0..20 {
+            let mut msg = Message::create_msg_str_fit(&format!("text {}", i));
+            msg.set_type(MessageType::TEXT as u8);
+            conn.post_message(msg);
+            let reply = conn.get_message().unwrap();
+            assert_eq!(reply.get_type(), MessageType::REPLY as u8);
+            assert_eq!(reply.get_content_str().unwrap(), format!("text {}", i));
+        }
+        for i in 0..200 {
+            conn.post_message(Message::create_msg_str_fit(&format!("burst {}", i)));
+        }
+        for i in 0..200 {
+            assert_eq!(conn.get_message().unwrap().get_content_str().unwrap(), format!("burst {}", i));
+        }
+        let mut msg = Message::create_msg_header_only();
+        msg.set_type(MessageType::END as u8);
+        conn.post_message(msg);
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn high_priority_fragments_overtake_bulk() {
+        let link = Negotiated { features: 0, max_frame_size: 1 << 20, peer_name: "test".to_string() };
+        let mut outbox = Outbox::<P,M,L>::new(link, 100);
+        outbox.post(Message::create_msg_bytes_fit(&[1u8; 1000]));
+        let mut urgent = Message::create_msg_bytes_fit(&[2u8; 300]);
+        urgent.set_priority(PRIORITY_HIGH);
+        outbox.post(urgent);
+        while !outbox.is_idle() {
+            outbox.send_fragment();
+        }
+        let priorities: Vec<u8> = outbox.ready.iter().map(|m| m.get_priority()).collect();
+        /*-- one bulk fragment was out before urgent arrived --*/
+        assert_eq!(priorities[0], PRIORITY_NORMAL);
+        assert!(priorities[1..4].iter().all(|&p| p == PRIORITY_HIGH));
+        assert!(priorities[4..].iter().all(|&p| p == PRIORITY_NORMAL));
+        assert_eq!(priorities.len(), 1 + 3 + 9);
+    }
+    fn text_msg(text: &str) -> Message {
+        let mut msg = Message::create_msg_str_fit(text);
+        msg.set_type(MessageType::TEXT as u8);
+        msg
+    }
+    #[test]
+    fn connector_drops_expired_messages() {
+        let addr = "127.0.0.1:8099";
+        let clock = Arc::new(ManualClock::new(10_000));
+        let options = CommOptions { 
+            clock: clock.clone(), notify_expired: true, ..CommOptions::default() 
+        };
+        let mut lsnr = Listener::<P,L>::new();
+        lsnr.set_options(options.clone());
+        let handle = lsnr.start(addr).unwrap();
+        let conn = Connector::<P,M,L>::with_options(addr, options).unwrap();
+        let mut stale = text_msg("stale");
+        stale.set_deadline(5_000);
+        conn.post_message(stale);
+        let mut fresh = text_msg("fresh");
+        fresh.set_ttl(Duration::from_secs(1), clock.as_ref());
+        conn.post_message(fresh);
+        let reply = conn.get_message().unwrap();
+        assert_eq!(reply.get_content_str().unwrap(), "fresh");
+        assert_eq!(conn.get_expired().unwrap().get_content_str().unwrap(), "stale");
+        assert!(!conn.has_msg());
+        /*-- a request dropped here still gets an answer --*/
+        let mut late = text_msg("late");
+        late.set_deadline(5_000);
+        let err = conn.request(late, Duration::from_secs(5)).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+        assert!(err.to_string().contains("expired"));
+        assert!(!conn.has_expired());
+        let mut msg = Message::create_msg_header_only();
+        msg.set_type(MessageType::END as u8);
+        conn.post_message(msg);
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn listener_answers_expired_requests() {
+        let addr = "127.0.0.1:8100";
+        let lsnr_clock = Arc::new(ManualClock::new(10_000));
+        let mut lsnr = Listener::<P,L>::new();
+        lsnr.set_options(CommOptions { 
+            clock: lsnr_clock.clone(), notify_expired: true, ..CommOptions::default() 
+        });
+        let handle = lsnr.start(addr).unwrap();
+        let conn_clock = Arc::new(ManualClock::new(0));
+        let options = CommOptions { clock: conn_clock.clone(), ..CommOptions::default() };
+        let conn = Connector::<P,M,L>::with_options(addr, options).unwrap();
+
+        /*-- sent in time by connector's clock, late by listener's --*/
+        let mut late = text_msg("late");
+        late.set_ttl(Duration::from_secs(1), conn_clock.as_ref());
+        conn.post_message(late);
+        let reply = conn.get_message().unwrap();
+        assert_eq!(reply.get_type(), MessageType::EXPIRED as u8);
+        assert_eq!(reply.get_content_str().unwrap(), "late");
+
+        lsnr_clock.set(500);
+        let mut on_time = text_msg("on time");
+        on_time.set_ttl(Duration::from_secs(1), conn_clock.as_ref());
+        conn.post_message(on_time);
+        let reply = conn.get_message().unwrap();
+        assert_eq!(reply.get_type(), MessageType::REPLY as u8);
+        assert_eq!(reply.get_content_str().unwrap(), "on time");
+        let mut msg = Message::create_msg_header_only();
+        msg.set_type(MessageType::END as u8);
+        conn.post_message(msg);
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn expired_reply_waits_for_earlier_request() {
+        let addr = "127.0.0.1:8106";
+        let lsnr_clock = Arc::new(ManualClock::new(10_000));
+        let mut lsnr = Listener::<SlowProcessing,L>::new();
+        lsnr.set_options(CommOptions { 
+            clock: lsnr_clock.clone(), notify_expired: true, ..CommOptions::default() 
+        });
+        let handle = lsnr.start(addr).unwrap();
+        let conn_clock = Arc::new(ManualClock::new(0));
+        let options = CommOptions { clock: conn_clock.clone(), ..CommOptions::default() };
+        let conn = Connector::<P,M,L>::with_options(addr, options).unwrap();
+
+        let slow = conn.post_request(text_msg("slow"));
+        let mut late = text_msg("late");
+        late.set_ttl(Duration::from_secs(1), conn_clock.as_ref());
+        conn.post_message(late);
+        thread::sleep(Duration::from_millis(100));
+        assert!(!conn.has_msg());
+        conn.cancel(slow);
+        assert_eq!(conn.get_message().unwrap().get_type(), MessageType::CANCELLED as u8);
+        assert_eq!(conn.get_message().unwrap().get_type(), MessageType::EXPIRED as u8);
+        let mut msg = Message::create_msg_header_only();
+        msg.set_type(MessageType::END as u8);
+        conn.post_message(msg);
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    /*-----------------------------------------------------
+      CommProcessing, except "slow" works until cancelled or
+      5 s pass, and "missing" is answered with an ERROR
+    */
+    #[derive(Debug, Copy, Clone, Default)]
+    struct SlowProcessing;
+    impl Sndr<M> for SlowProcessing {
+        fn send_message(msg: &M, stream: &mut TcpStream) -> Result<()> {
+            P::send_message(msg, stream)
+        }
+        fn buf_send_message(msg: &M, stream: &mut BufWriter<TcpStream>) -> Result<()> {
+            <P as Sndr<M>>::buf_send_message(msg, stream)
+        }
+    }
+    impl Rcvr<M> for SlowProcessing {
+        fn recv_message(stream: &mut TcpStream) -> Result<M> {
+            P::recv_message(stream)
+        }
+        fn buf_recv_message(stream: &mut BufReader<TcpStream>) -> Result<M> {
+            P::buf_recv_message(stream)
+        }
+        fn buf_recv_message_limited(stream: &mut BufReader<TcpStream>, max_frame: u64) -> Result<M> {
+            P::buf_recv_message_limited(stream, max_frame)
+        }
+    }
+    impl Process<M> for SlowProcessing {
+        fn process_message(msg: &mut M) {
+            P::process_message(msg);
+        }
+        fn process_cancellable(msg: &mut M, token: &CancelToken) {
+            let start = std::time::Instant::now();
+            while msg.get_content_str() == Ok("slow") 
+                && !token.is_cancelled() 
+                && start.elapsed() < Duration::from_secs(5) 
+            {
+                thread::sleep(Duration::from_millis(1));
+            }
+            if msg.get_content_str() == Ok("missing") {
+                msg.set_error(STATUS_NOT_FOUND, "no such item");
+                return;
+            }
+            Self::process_message(msg);
+        }
+    }
+    #[test]
+    fn cancel_stops_running_and_queued_requests() {
+        let addr = "127.0.0.1:8101";
+        let mut lsnr = Listener::<SlowProcessing,L>::new();
+        let handle = lsnr.start(addr).unwrap();
+        let conn = Connector::<P,M,L>::new(addr).unwrap();
+        let start = std::time::Instant::now();
+        let slow = conn.post_request(text_msg("slow"));
+        let queued = conn.post_request(text_msg("queued"));
+        let fast = conn.post_request(text_msg("fast"));
+        conn.cancel(slow);
+        conn.cancel(queued);
+        let replies: Vec<Message> = (0..3).map(|_| conn.get_message().unwrap()).collect();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(replies[0].get_request_id(), slow);
+        assert_eq!(replies[0].get_type(), MessageType::CANCELLED as u8);
+        assert_eq!(replies[0].get_content_size(), 0);
+        assert_eq!(replies[1].get_request_id(), queued);
+        assert_eq!(replies[1].get_type(), MessageType::CANCELLED as u8);
+        assert_eq!(replies[2].get_request_id(), fast);
+        assert_eq!(replies[2].get_type(), MessageType::REPLY as u8);
+        assert_eq!(replies[2].get_content_str().unwrap(), "fast");
+        let mut msg = Message::create_msg_header_only();
+        msg.set_type(MessageType::END as u8);
+        conn.post_message(msg);
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn request_surfaces_error_replies() {
+        let addr = "127.0.0.1:8102";
+        let mut lsnr = Listener::<SlowProcessing,L>::new();
+        let handle = lsnr.start(addr).unwrap();
+        let conn = Connector::<P,M,L>::new(addr).unwrap();
+
+        /*-- unrelated reply arriving first stays for get_message --*/
+        conn.post_message(text_msg("unrelated"));
+        let reply = conn.request(text_msg("found"), Duration::from_secs(5)).unwrap();
+        assert_eq!(reply.get_type(), MessageType::REPLY as u8);
+        assert_eq!(reply.get_content_str().unwrap(), "found");
+
+        let err = conn.request(text_msg("missing"), Duration::from_secs(5)).unwrap_err();
+        assert!(is_error_reply(&err));
+        let reply = error_reply(&err).unwrap();
+        assert_eq!(reply.status, STATUS_NOT_FOUND);
+        assert_eq!(reply.message, "no such item");
+        assert_eq!(conn.get_message().unwrap().get_content_str().unwrap(), "unrelated");
+        assert!(!conn.has_msg());
+        let mut msg = Message::create_msg_header_only();
+        msg.set_type(MessageType::END as u8);
+        conn.post_message(msg);
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn concurrent_requests_get_their_own_replies() {
+        let addr = "127.0.0.1:8104";
+        let mut lsnr = Listener::<P,L>::new();
+        let handle = lsnr.start(addr).unwrap();
+        let conn = Connector::<P,M,L>::new(addr).unwrap();
+        thread::scope(|s| {
+            for t in 0..4 {
+                let conn = &conn;
+                s.spawn(move || {
+                    for i in 0..20 {
+                        let text = format!("caller {} request {}", t, i);
+                        let reply = conn.request(text_msg(&text), Duration::from_secs(5)).unwrap();
+                        assert_eq!(reply.get_content_str().unwrap(), text);
+                    }
+                });
+            }
+            for i in 0..20 {
+                conn.post_message(text_msg(&format!("posted {}", i)));
+            }
+        });
+        for i in 0..20 {
+            assert_eq!(conn.get_message().unwrap().get_content_str().unwrap(), format!("posted {}", i));
+        }
+        assert!(!conn.has_msg());
+        let mut msg = Message::create_msg_header_only();
+        msg.set_type(MessageType::END as u8);
+        conn.post_message(msg);
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn request_times_out_and_sees_lost_connection() {
+        let addr = "127.0.0.1:8105";
+        let mut lsnr = Listener::<SlowProcessing,L>::new();
+        let handle = lsnr.start(addr).unwrap();
+        let conn = Connector::<P,M,L>::new(addr).unwrap();
+        let err = conn.request(text_msg("slow"), Duration::from_millis(100)).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+        /*-- timed out request was cancelled, its late answer is kept --*/
+        assert_eq!(conn.get_message().unwrap().get_type(), MessageType::CANCELLED as u8);
+
+        let mut msg = Message::create_msg_header_only();
+        msg.set_type(MessageType::END as u8);
+        conn.post_message(msg);
+        lsnr.stop();
+        let _ = handle.join();
+        let start = std::time::Instant::now();
+        let err = conn.request(text_msg("anyone?"), Duration::from_secs(5)).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::ConnectionAborted);
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(conn.get_message().is_none());
+        assert!(conn.get_expired().is_none());
+    }
+    /*-- fresh, empty directory under the system temp dir --*/
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rust_comm_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+    #[test]
+    fn file_transfer_resumes() {
+        let addr = "127.0.0.1:8096";
+        let src_dir = temp_dir("file_src");
+        let sink_dir = temp_dir("file_sink");
+        let data: Vec<u8> = (0..300_000u32).map(|i| (i % 253) as u8).collect();
+        let src = src_dir.join("data.bin");
+        std::fs::write(&src, &data).unwrap();
+        /*-- sink already holds the first 100_000 bytes --*/
+        std::fs::write(sink_dir.join("data.bin.part"), &data[..100_000]).unwrap();
+
+        let mut lsnr = Listener::<P,L>::new();
+        lsnr.set_sink_dir(&sink_dir);
+        let handle = lsnr.start(addr).unwrap();
+        let conn = Connector::<P,M,L>::new(addr).unwrap();
+        let sent = conn.send_file(&src).unwrap();
+        assert_eq!(sent, 200_000);
+        assert_eq!(std::fs::read(sink_dir.join("data.bin")).unwrap(), data);
+        assert!(!sink_dir.join("data.bin.part").exists());
+        let sent = conn.send_file_from(&src, 0).unwrap();
+        assert_eq!(sent, data.len() as u64);
+
+        let mut msg = Message::create_msg_header_only();
+        msg.set_type(MessageType::END as u8);
+        conn.post_message(msg);
+        lsnr.stop();
+        let _ = handle.join();
+    }
+    #[test]
+    fn file_transfer_detects_bad_part() {
+        let addr = "127.0.0.1:8097";
+        let src_dir = temp_dir("bad_src");
+        let sink_dir = temp_dir("bad_sink");
+        let data = vec![1u8; 50_000];
+        let src = src_dir.join("ones.bin");
+        std::fs::write(&src, &data).unwrap();
+        std::fs::write(sink_dir.join("ones.bin.part"), vec![0u8; 10_000]).unwrap();
+
+        let mut lsnr = Listener::<P,L>::new();
+        lsnr.set_sink_dir(&sink_dir);
+        let handle = lsnr.start(addr).unwrap();
+        let conn = Connector::<P,M,L>::new(addr).unwrap();
+        let err = conn.send_file(&src).unwrap_err();
+        assert!(err.to_string().contains("sha256 mismatch"));
+        assert!(!sink_dir.join("ones.bin").exists());
+        assert!(!sink_dir.join("ones.bin.part").exists());
+        /*-- retry starts over and succeeds --*/
+        assert_eq!(conn.send_file(&src).unwrap(), data.len() as u64);
+        assert_eq!(std::fs::read(sink_dir.join("ones.bin")).unwrap(), data);
+
+        let mut msg = Message::create_msg_header_only();
+        msg.set_type(MessageType::END as u8);
+        conn.post_message(msg);
+        lsnr.stop();
+        let _ = handle.join();
+    }
 }
@@ -0,0 +1,99 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::reply_slots.rs - replies awaited by request  //
+//                                                         //
+// Jim Fawcett, https://JimFawcett.github.io, 18 Oct 2026  //
+/////////////////////////////////////////////////////////////
+/*
+   ReplySlots<M>:
+   - routes replies to the Connector::request calls waiting
+     for them, keyed by request id
+   - each call waits on its own slot, a BlockingQueue, so
+     concurrent callers can't take each other's replies and
+     messages nobody waits for stay in arrival order
+   - close(), when the receive thread exits, closes every
+     slot and refuses new ones, so no call waits on a dead
+     connection
+*/
+
+use rust_traits::*;
+use rust_blocking_queue::BlockingQueue;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+type Slot<M> = Arc<BlockingQueue<M>>;
+
+#[derive(Debug)]
+pub(crate) struct ReplySlots<M> {
+    slots: Mutex<Option<HashMap<u64, Slot<M>>>>,  // None once closed
+}
+impl<M: Msg> ReplySlots<M> {
+    pub(crate) fn new() -> ReplySlots<M> {
+        ReplySlots { slots: Mutex::new(Some(HashMap::new())) }
+    }
+    /*-- slot for reply to request id, None once closed --*/
+    pub(crate) fn open(&self, id: u64) -> Option<Slot<M>> {
+        let mut slots = self.slots.lock().unwrap();
+        let slot = Arc::new(BlockingQueue::with_capacity(1));
+        slots.as_mut()?.insert(id, Arc::clone(&slot));
+        Some(slot)
+    }
+    /*-- caller stopped waiting, a late reply goes to get_message --*/
+    pub(crate) fn forget(&self, id: u64) {
+        if let Some(slots) = self.slots.lock().unwrap().as_mut() {
+            slots.remove(&id);
+        }
+    }
+    /*-- hands msg to the call waiting for it, else returns msg --*/
+    pub(crate) fn deliver(&self, msg: M) -> Option<M> {
+        let slot = match self.slots.lock().unwrap().as_mut() {
+            Some(slots) => slots.remove(&msg.get_request_id()),
+            None => None,
+        };
+        match slot {
+            Some(slot) => slot.try_en_q(msg).err(),
+            None => Some(msg),
+        }
+    }
+    pub(crate) fn close(&self) {
+        if let Some(slots) = self.slots.lock().unwrap().take() {
+            for slot in slots.values() {
+                slot.close();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_message::*;
+
+    fn reply(id: u64) -> Message {
+        let mut msg = Message::create_msg_header_only();
+        msg.set_request_id(id);
+        msg
+    }
+    #[test]
+    fn replies_reach_their_own_slot() {
+        let slots = ReplySlots::<Message>::new();
+        let one = slots.open(1).unwrap();
+        let two = slots.open(2).unwrap();
+        assert!(slots.deliver(reply(2)).is_none());
+        assert!(slots.deliver(reply(3)).is_some());
+        assert_eq!(two.try_de_q().unwrap().get_request_id(), 2);
+        assert!(one.try_de_q().is_none());
+        slots.forget(1);
+        assert!(slots.deliver(reply(1)).is_some());
+    }
+    #[test]
+    fn close_wakes_waiters_and_refuses_new() {
+        let slots = ReplySlots::<Message>::new();
+        let slot = slots.open(1).unwrap();
+        slots.close();
+        assert!(slot.is_closed());
+        assert!(slot.de_q().is_none());
+        assert!(slots.open(2).is_none());
+        assert!(slots.deliver(reply(1)).is_some());
+    }
+}
@@ -0,0 +1,93 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::send_queue.rs - priority ordered send queue  //
+//                                                         //
+// Jim Fawcett, https://JimFawcett.github.io, 18 Oct 2026  //
+/////////////////////////////////////////////////////////////
+/*
+   SendQueue<M>:
+   - blocking queue like BlockingQueue<M>, but de_q returns
+     the message with highest priority, see Msg::get_priority
+   - messages of equal priority leave in the order posted
+   - close() lets a blocked reader finish, de_q returns None
+     once the queue is closed and empty
+   Ordering and blocking come from rust_blocking_queue's
+   PriorityQueue, keyed on message priority.
+*/
+
+use rust_traits::*;
+use rust_blocking_queue::PriorityQueue;
+
+#[derive(Debug)]
+pub struct SendQueue<M> {
+    q: PriorityQueue<M, u8, fn(&M) -> u8>,
+}
+impl<M: Msg> Default for SendQueue<M> {
+    fn default() -> SendQueue<M> {
+        SendQueue::new()
+    }
+}
+impl<M: Msg> SendQueue<M> {
+    pub fn new() -> SendQueue<M> {
+        SendQueue { q: PriorityQueue::new(M::get_priority) }
+    }
+    /*-- messages posted after close are dropped --*/
+    pub fn en_q(&self, msg: M) {
+        let _ = self.q.en_q(msg);
+    }
+    /*-- waits until fewer than limit messages are queued, dropped after close --*/
+    pub fn en_q_below(&self, msg: M, limit: usize) {
+        let _ = self.q.en_q_below(msg, limit);
+    }
+    /*-- blocks while empty, None once closed and drained --*/
+    pub fn de_q(&self) -> Option<M> {
+        self.q.de_q()
+    }
+    /*-- priority of the message de_q would return --*/
+    pub fn peek_priority(&self) -> Option<u8> {
+        self.q.peek_key()
+    }
+    pub fn len(&self) -> usize {
+        self.q.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.q.is_empty()
+    }
+    /*-- queued messages are still delivered --*/
+    pub fn close(&self) {
+        self.q.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_message::*;
+
+    fn msg(text: &str, priority: u8) -> Message {
+        let mut msg = Message::create_msg_str_fit(text);
+        msg.set_priority(priority);
+        msg
+    }
+    #[test]
+    fn higher_priority_first_fifo_within() {
+        let q = SendQueue::<Message>::new();
+        q.en_q(msg("bulk 1", PRIORITY_NORMAL));
+        q.en_q(msg("bulk 2", PRIORITY_NORMAL));
+        q.en_q(msg("high", PRIORITY_HIGH));
+        q.en_q(msg("quit", PRIORITY_CONTROL));
+        q.en_q(msg("bulk 3", PRIORITY_NORMAL));
+        assert_eq!(q.peek_priority(), Some(PRIORITY_CONTROL));
+        let order: Vec<String> = (0..5)
+            .map(|_| q.de_q().unwrap().get_content_str().unwrap().to_string())
+            .collect();
+        assert_eq!(order, ["quit", "high", "bulk 1", "bulk 2", "bulk 3"]);
+    }
+    #[test]
+    fn close_drains_then_ends() {
+        let q = SendQueue::<Message>::new();
+        q.en_q(msg("last", PRIORITY_NORMAL));
+        q.close();
+        assert!(q.de_q().is_some());
+        assert!(q.de_q().is_none());
+    }
+}
@@ -18,26 +18,25 @@ type Log = MuteLog;
 
 fn handle_client(stream: &TcpStream) -> std::io::Result<()> {
     let mut clone_stream = stream.try_clone()?;
-    let rslt = CommProcessing::<Log>::recv_message(&mut clone_stream);
-    if rslt.is_err() {
-        print!("\n  recv_message error");
-        let err = std::io::Error::new(ErrorKind::Other, "recv error");
-        return Err(err);
-    }
-    else {
-        let msg:Message = rslt.unwrap();
+    let rslt:Result<Message> = CommProcessing::<Log>::recv_message(&mut clone_stream);
+    if let Ok(msg) = rslt {
         print!("\n  receiver received msg");
         msg.show_message(8);
         CommProcessing::<Log>::send_message(&msg, &mut clone_stream)?;
     }
+    else {
+        print!("\n  recv_message error");
+        let err = std::io::Error::other("recv error");
+        return Err(err);
+    }
     Ok(())
 }
 fn start_listener(end_point: &str) -> std::io::Result<()> {
     let tcpl = TcpListener::bind(end_point)?;
-    for stream in tcpl.incoming() {
+    /*-- only one connection for testing --*/
+    if let Some(stream) = tcpl.incoming().next() {
         print!("\n  listener accepted connection");
         handle_client(&stream?)?;
-        break;  // only one connection for testing
     }
     Ok(())
 }
@@ -21,29 +21,28 @@ fn handle_client(stream: &TcpStream) -> std::io::Result<()> {
     let mut buf_reader = BufReader::new(stream.try_clone()?);
 
     let rslt:Result<Message> = CommProcessing::<Log>::buf_recv_message(&mut buf_reader);
-    if rslt.is_err() {
-        print!("\n  recv_message error");
-        let err = std::io::Error::new(ErrorKind::Other, "recv error");
-        return Err(err);
-    }
-    else {
+    if let Ok(msg) = rslt {
         print!("\n  receiver received msg");
-        let msg = rslt.unwrap();
         msg.show_message(8);
         CommProcessing::<Log>::buf_send_message(&msg, &mut buf_writer)?;
     }
+    else {
+        print!("\n  recv_message error");
+        let err = std::io::Error::other("recv error");
+        return Err(err);
+    }
     Ok(())
 }
 fn start_listener(end_point: &str) -> std::io::Result<()> {
     let tcpl = TcpListener::bind(end_point)?;
-    for stream in tcpl.incoming() {
+    /*-- only one connection for testing --*/
+    if let Some(stream) = tcpl.incoming().next() {
         print!("\n  listener accepted connection");
         let rslt = handle_client(&stream?);
         if rslt.is_err() {
             print!("\n  error in handle_client");
             let _ = std::io::stdout().flush();
         }
-        break;  // only one connection for testing
     }
     Ok(())
 }
@@ -0,0 +1,92 @@
+/////////////////////////////////////////////////////////////
+// rust_comm_processing::test3.rs - buffer pool benchmark  //
+//                                                         //
+// Jim Fawcett, https://JimFawcett.github.io, 18 Oct 2026  //
+/////////////////////////////////////////////////////////////
+/*
+   Receives a stream of messages with the global buffer pool
+   disabled, allocating a body per message as before, then
+   with it enabled, and compares receive rates.
+*/
+
+use std::net::{TcpListener, TcpStream};
+
+use rust_traits::*;
+use rust_comm_processing::*;
+use rust_message::*;
+use rust_comm_logger::*;
+use std::io::*;
+use std::time::Instant;
+
+type Log = MuteLog;
+type CP = CommProcessing<Log>;
+
+const BYTES_PER_RUN: usize = 64 * 1024 * 1024;
+
+fn stream_pair() -> Result<(TcpStream, TcpStream)> {
+    let lsnr = TcpListener::bind("127.0.0.1:0")?;
+    let client = TcpStream::connect(lsnr.local_addr()?)?;
+    let (server, _) = lsnr.accept()?;
+    Ok((client, server))
+}
+/*-- returns receive time in microseconds --*/
+fn run(body_size: usize, count: usize) -> Result<u128> {
+    let (client, server) = stream_pair()?;
+    let sender = std::thread::spawn(move || -> Result<()> {
+        let mut buf_writer = BufWriter::new(client);
+        let msg = Message::create_msg_bytes_fit(&vec![b'a'; body_size]);
+        for _ in 0..count {
+            CP::buf_send_message(&msg, &mut buf_writer)?;
+        }
+        buf_writer.flush()
+    });
+    let mut buf_reader = BufReader::new(server);
+    let start = Instant::now();
+    for _ in 0..count {
+        let msg: Message = CP::buf_recv_message(&mut buf_reader)?;
+        drop(msg);
+    }
+    let elapsed = start.elapsed().as_micros().max(1);
+    let _ = sender.join();
+    Ok(elapsed)
+}
+fn show(label: &str, body_size: usize, count: usize, microsec: u128) {
+    let secs = microsec as f64 / 1_000_000.0;
+    let mb = (body_size * count) as f64 / (1024.0 * 1024.0);
+    print!(
+        "\n    {:<9} {:>10.0} msgs/sec {:>9.1} MB/S",
+        label, count as f64 / secs, mb / secs
+    );
+}
+
+fn main() -> Result<()> {
+
+    print!("\n  -- test3 : receive buffer pool\n");
+
+    let pool = global_pool();
+    for body_size in [4 * 1024, 64 * 1024, 1024 * 1024] {
+        let count = (BYTES_PER_RUN / body_size).max(1);
+        print!("\n  {} msgs, {} bytes content per msg", count, body_size);
+
+        pool.set_enabled(false);
+        let microsec = run(body_size, count)?;
+        show("allocate", body_size, count, microsec);
+
+        pool.set_enabled(true);
+        let before = pool.stats();
+        let microsec = run(body_size, count)?;
+        show("pooled", body_size, count, microsec);
+        let after = pool.stats();
+        let delta = PoolStats {
+            hits: after.hits - before.hits,
+            misses: after.misses - before.misses,
+            ..after
+        };
+        print!(
+            "\n    pool hits {}, misses {}, hit rate {:.3}\n",
+            delta.hits, delta.misses, delta.hit_rate()
+        );
+    }
+    print!("\n  That's all Folks!\n\n");
+    Ok(())
+}
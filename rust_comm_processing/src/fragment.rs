@@ -0,0 +1,418 @@
+/////////////////////////////////////////////////////////////
+// rust_comm_processing::fragment.rs - split and rejoin    //
+//                                                         //
+// Jim Fawcett, https://JimFawcett.github.io, 18 Oct 2026  //
+/////////////////////////////////////////////////////////////
+/*
+   Large message bodies are sent as a sequence of FRAGMENT
+   messages so they don't hold the link while smaller
+   messages wait.
+
+   Fragment body:
+       id           u64   same for every fragment of a message
+       seq          u32   0, 1, 2, ...
+       total        u64   content size of the original message
+       orig type    u8
+       orig flags   u8
+       chunk        rest of body
+
+   Fragment headers carry the original message's priority,
+   deadline, and request id.  Its key/value headers ride on
+   the first fragment only, which carries that much less
+   content, since the receiver counts headers and content
+   together against its max frame size.
+
+   Fragmenter<M> yields fragments of one message.
+   Reassembler<M> collects them on the receiving side, holding
+   at most `cap` bytes of partial messages at a time.
+*/
+
+use rust_traits::*;
+use rust_message::*;
+
+use std::collections::HashMap;
+use std::fmt;
+
+pub const FRAGMENT_HEADER_SIZE: usize = 8 + 4 + 8 + 1 + 1;
+pub const DEFAULT_FRAGMENT_SIZE: usize = 64 * 1024;
+pub const DEFAULT_REASSEMBLY_CAP: usize = 256 * 1024 * 1024;
+
+/*-- largest chunk that keeps headerless fragment frames under max_frame --*/
+pub fn fragment_size_for(preferred: usize, max_frame: u64) -> usize {
+    let overhead = (HEADER_SIZE + FRAGMENT_HEADER_SIZE + 8) as u64;
+    let limit = max_frame.saturating_sub(overhead).max(1);
+    (preferred as u64).min(limit).max(1) as usize
+}
+
+/*-- headers count against the frame too --*/
+pub fn needs_fragmenting<M: Msg>(msg: &M, fragment_size: usize) -> bool {
+    msg.get_type() != MessageType::FRAGMENT as u8
+        && msg.get_content_size() + msg.get_meta().len() > fragment_size
+}
+
+/*---------------------------------------------------------
+  Fragmenter<M> - iterator over fragments of one message
+*/
+#[derive(Debug)]
+pub struct Fragmenter<M: Msg> {
+    msg: M,
+    id: u64,
+    seq: u32,
+    offset: usize,
+    fragment_size: usize,
+}
+impl<M: Msg> Fragmenter<M> {
+    pub fn new(msg: M, id: u64, fragment_size: usize) -> Fragmenter<M> {
+        Fragmenter { msg, id, seq: 0, offset: 0, fragment_size: fragment_size.max(1) }
+    }
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+    pub fn priority(&self) -> u8 {
+        self.msg.get_priority()
+    }
+    /*-- type of the message being fragmented --*/
+    pub fn msg_type(&self) -> u8 {
+        self.msg.get_type()
+    }
+    pub fn is_done(&self) -> bool {
+        self.offset >= self.msg.get_content_size() && self.seq > 0
+    }
+}
+impl<M: Msg> Iterator for Fragmenter<M> {
+    type Item = M;
+    fn next(&mut self) -> Option<M> {
+        if self.is_done() {
+            return None;
+        }
+        let total = self.msg.get_content_size();
+        let meta = if self.seq == 0 { self.msg.get_meta() } else { Bytes::new() };
+        let room = self.fragment_size.saturating_sub(meta.len()).max(1);
+        let end = (self.offset + room).min(total);
+        let chunk = &self.msg.get_content_bytes()[self.offset..end];
+
+        let mut body = Vec::with_capacity(FRAGMENT_HEADER_SIZE + chunk.len());
+        body.extend_from_slice(&self.id.to_be_bytes());
+        body.extend_from_slice(&self.seq.to_be_bytes());
+        body.extend_from_slice(&(total as u64).to_be_bytes());
+        body.push(self.msg.get_type());
+        body.push(self.msg.get_flags());
+        body.extend_from_slice(chunk);
+
+        let mut frag = M::new(HEADER_SIZE);
+        frag.set_type(MessageType::FRAGMENT as u8);
+        frag.set_priority(self.msg.get_priority());
+        frag.set_deadline(self.msg.get_deadline());
+        frag.set_request_id(self.msg.get_request_id());
+        frag.set_meta(meta);
+        frag.set_body(Bytes::from(body));
+        self.offset = end;
+        self.seq += 1;
+        Some(frag)
+    }
+}
+
+/*---------------------------------------------------------
+  ReassemblyError - fragment dropped, link is still usable
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReassemblyError {
+    /*-- partial messages would exceed the memory cap --*/
+    CapExceeded { id: u64, total: usize, cap: usize },
+    /*-- fragment doesn't continue a message in progress --*/
+    OutOfSequence { id: u64, seq: u32 },
+    Malformed,
+}
+impl fmt::Display for ReassemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReassemblyError::CapExceeded { id, total, cap } => write!(
+                f, "fragmented message {} of {} bytes exceeds reassembly cap {}",
+                id, total, cap
+            ),
+            ReassemblyError::OutOfSequence { id, seq } => write!(
+                f, "fragment {} of message {} out of sequence", seq, id
+            ),
+            ReassemblyError::Malformed => write!(f, "malformed fragment"),
+        }
+    }
+}
+impl std::error::Error for ReassemblyError {}
+
+struct FragmentHeader {
+    id: u64,
+    seq: u32,
+    total: usize,
+    msg_type: u8,
+    flags: u8,
+}
+fn parse_header(body: &[u8]) -> Option<FragmentHeader> {
+    if body.len() < FRAGMENT_HEADER_SIZE {
+        return None;
+    }
+    let mut id = [0u8; 8];
+    id.copy_from_slice(&body[0..8]);
+    let mut seq = [0u8; 4];
+    seq.copy_from_slice(&body[8..12]);
+    let mut total = [0u8; 8];
+    total.copy_from_slice(&body[12..20]);
+    Some(FragmentHeader {
+        id: u64::from_be_bytes(id),
+        seq: u32::from_be_bytes(seq),
+        total: u64::from_be_bytes(total) as usize,
+        msg_type: body[20],
+        flags: body[21],
+    })
+}
+
+#[derive(Debug)]
+struct Partial {
+    msg_type: u8,
+    flags: u8,
+    priority: u8,
+    deadline: u64,
+    request_id: u64,
+    meta: Bytes,
+    total: usize,
+    body: Vec<u8>,
+    next_seq: u32,
+}
+
+/*---------------------------------------------------------
+  Reassembler<M> - one per receiving link
+  - non-fragment messages pass straight through
+  - fragments of a message that can't be kept are skipped
+    until its last fragment goes by
+*/
+#[derive(Debug)]
+pub struct Reassembler<M: Msg> {
+    _m: std::marker::PhantomData<M>,
+    partial: HashMap<u64, Partial>,
+    skipping: HashMap<u64, usize>,   // id -> bytes still to skip
+    buffered: usize,
+    cap: usize,
+}
+impl<M: Msg> Default for Reassembler<M> {
+    fn default() -> Reassembler<M> {
+        Reassembler::new(DEFAULT_REASSEMBLY_CAP)
+    }
+}
+impl<M: Msg> Reassembler<M> {
+    pub fn new(cap: usize) -> Reassembler<M> {
+        Reassembler {
+            _m: std::marker::PhantomData,
+            partial: HashMap::new(),
+            skipping: HashMap::new(),
+            buffered: 0,
+            cap,
+        }
+    }
+    /*-- bytes held by partial messages --*/
+    pub fn buffered(&self) -> usize {
+        self.buffered
+    }
+    /*-- returns completed message, if msg finished one --*/
+    pub fn accept(&mut self, msg: M) -> Result<Option<M>, ReassemblyError> {
+        if msg.get_type() != MessageType::FRAGMENT as u8 {
+            return Ok(Some(msg));
+        }
+        let body = msg.get_content_bytes();
+        let hdr = parse_header(body).ok_or(ReassemblyError::Malformed)?;
+        let chunk = &body[FRAGMENT_HEADER_SIZE..];
+
+        if let Some(left) = self.skipping.get_mut(&hdr.id) {
+            *left = left.saturating_sub(chunk.len());
+            if *left == 0 {
+                self.skipping.remove(&hdr.id);
+            }
+            return Ok(None);
+        }
+        if hdr.seq == 0 {
+            if self.partial.contains_key(&hdr.id) {
+                return Err(self.abandon(hdr.id, hdr.seq, hdr.total, chunk.len()));
+            }
+            /*-- total comes off the wire, may be absurd --*/
+            let fits = self.buffered.checked_add(hdr.total)
+                .is_some_and(|held| held <= self.cap);
+            if !fits {
+                self.skip(hdr.id, hdr.total, chunk.len());
+                return Err(ReassemblyError::CapExceeded {
+                    id: hdr.id, total: hdr.total, cap: self.cap,
+                });
+            }
+            self.buffered += hdr.total;
+            self.partial.insert(hdr.id, Partial {
+                msg_type: hdr.msg_type,
+                flags: hdr.flags,
+                priority: msg.get_priority(),
+                deadline: msg.get_deadline(),
+                request_id: msg.get_request_id(),
+                meta: msg.get_meta(),
+                total: hdr.total,
+                body: Vec::with_capacity(hdr.total),
+                next_seq: 0,
+            });
+        }
+        let part = match self.partial.get_mut(&hdr.id) {
+            Some(part) => part,
+            None => return Err(ReassemblyError::OutOfSequence { id: hdr.id, seq: hdr.seq }),
+        };
+        /*-- bounds come from seq 0, later fragments must agree --*/
+        let total = part.total;
+        if hdr.total != total || part.next_seq != hdr.seq
+            || part.body.len() + chunk.len() > total {
+            return Err(self.abandon(hdr.id, hdr.seq, total, chunk.len()));
+        }
+        part.body.extend_from_slice(chunk);
+        part.next_seq += 1;
+        if part.body.len() < total {
+            return Ok(None);
+        }
+        self.buffered -= total;
+        Ok(self.partial.remove(&hdr.id).map(|part| {
+            let mut whole = M::new(HEADER_SIZE);
+            whole.set_type(part.msg_type);
+            whole.set_flags(part.flags);
+            whole.set_priority(part.priority);
+            whole.set_deadline(part.deadline);
+            whole.set_request_id(part.request_id);
+            whole.set_meta(part.meta);
+            whole.set_body(Bytes::from(part.body));
+            whole
+        }))
+    }
+    fn skip(&mut self, id: u64, total: usize, seen: usize) {
+        if total > seen {
+            self.skipping.insert(id, total - seen);
+        }
+    }
+    /*-- drop message in progress, skip the rest of its fragments --*/
+    fn abandon(&mut self, id: u64, seq: u32, total: usize, seen: usize) -> ReassemblyError {
+        let filled = match self.partial.remove(&id) {
+            Some(part) => {
+                self.buffered -= part.total;
+                part.body.len()
+            }
+            None => 0,
+        };
+        self.skip(id, total, filled + seen);
+        ReassemblyError::OutOfSequence { id, seq }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body(n: usize) -> Vec<u8> {
+        (0..n).map(|i| (i % 251) as u8).collect()
+    }
+    #[test]
+    fn fragment_and_reassemble() {
+        let content = body(10_000);
+        let mut msg = Message::create_msg_bytes_fit(&content);
+        msg.set_type(MessageType::FLUSH as u8);
+        msg.set_flags(FLAG_JSON);
+        msg.set_priority(PRIORITY_HIGH);
+        msg.set_deadline(123_456);
+        msg.set_request_id(99);
+        msg.set_header("trace-id", "4bf92f35").unwrap();
+        assert!(needs_fragmenting(&msg, 4096));
+        let frags: Vec<Message> = Fragmenter::new(msg, 7, 4096).collect();
+        assert_eq!(frags.len(), 3);
+        assert_eq!(frags[1].get_header("trace-id"), None);
+
+        let mut rsm = Reassembler::<Message>::new(1 << 20);
+        assert!(rsm.accept(frags[0].clone()).unwrap().is_none());
+        assert!(rsm.accept(frags[1].clone()).unwrap().is_none());
+        assert_eq!(rsm.buffered(), 10_000);
+        let whole = rsm.accept(frags[2].clone()).unwrap().unwrap();
+        assert_eq!(whole.get_type(), MessageType::FLUSH as u8);
+        assert_eq!(whole.get_flags(), FLAG_JSON);
+        assert_eq!(whole.get_priority(), PRIORITY_HIGH);
+        assert_eq!(whole.get_deadline(), 123_456);
+        assert_eq!(whole.get_request_id(), 99);
+        assert_eq!(whole.get_header("trace-id"), Some("4bf92f35"));
+        assert_eq!(whole.get_content_bytes(), &content[..]);
+        assert_eq!(rsm.buffered(), 0);
+    }
+    #[test]
+    fn interleaved_messages_pass_through() {
+        let mut rsm = Reassembler::<Message>::default();
+        let mut a = Fragmenter::new(Message::create_msg_bytes_fit(&body(300)), 1, 100);
+        let mut b = Fragmenter::new(Message::create_msg_bytes_fit(&body(200)), 2, 100);
+        let small = Message::create_msg_str_fit("small");
+        assert!(rsm.accept(a.next().unwrap()).unwrap().is_none());
+        assert!(rsm.accept(b.next().unwrap()).unwrap().is_none());
+        let passed = rsm.accept(small).unwrap().unwrap();
+        assert_eq!(passed.get_content_str().unwrap(), "small");
+        assert!(rsm.accept(a.next().unwrap()).unwrap().is_none());
+        let whole_b = rsm.accept(b.next().unwrap()).unwrap().unwrap();
+        assert_eq!(whole_b.get_content_size(), 200);
+        let whole_a = rsm.accept(a.next().unwrap()).unwrap().unwrap();
+        assert_eq!(whole_a.get_content_bytes(), &body(300)[..]);
+        assert!(a.next().is_none());
+    }
+    #[test]
+    fn cap_exceeded_skips_message() {
+        let mut rsm = Reassembler::<Message>::new(1000);
+        let frags: Vec<Message> =
+            Fragmenter::new(Message::create_msg_bytes_fit(&body(2000)), 3, 500).collect();
+        match rsm.accept(frags[0].clone()) {
+            Err(ReassemblyError::CapExceeded { id: 3, .. }) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        for frag in &frags[1..] {
+            assert!(rsm.accept(frag.clone()).unwrap().is_none());
+        }
+        assert_eq!(rsm.buffered(), 0);
+        let next = Message::create_msg_str_fit("after");
+        assert!(rsm.accept(next).unwrap().is_some());
+    }
+    #[test]
+    fn absurd_total_rejected_before_allocating() {
+        let mut rsm = Reassembler::<Message>::new(1000);
+        let mut frags = Fragmenter::new(Message::create_msg_bytes_fit(&body(200)), 4, 100);
+        assert!(rsm.accept(frags.next().unwrap()).unwrap().is_none());
+        let mut forged = frags.next().unwrap();
+        let mut bytes = forged.get_content_bytes().to_vec();
+        bytes[0..8].copy_from_slice(&5u64.to_be_bytes());
+        bytes[8..12].copy_from_slice(&0u32.to_be_bytes());
+        bytes[12..20].copy_from_slice(&u64::MAX.to_be_bytes());
+        forged.set_body(Bytes::from(bytes));
+        match rsm.accept(forged) {
+            Err(ReassemblyError::CapExceeded { id: 5, .. }) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(rsm.buffered(), 200);
+    }
+    #[test]
+    fn mismatched_total_abandons_message() {
+        fn forge(frag: &Message, total: u64) -> Message {
+            let mut forged = frag.clone();
+            let mut bytes = forged.get_content_bytes().to_vec();
+            bytes[12..20].copy_from_slice(&total.to_be_bytes());
+            forged.set_body(Bytes::from(bytes));
+            forged
+        }
+        let frags: Vec<Message> =
+            Fragmenter::new(Message::create_msg_bytes_fit(&body(300)), 6, 100).collect();
+        for total in [10_000, 200] {
+            let mut rsm = Reassembler::<Message>::new(1000);
+            assert!(rsm.accept(frags[0].clone()).unwrap().is_none());
+            match rsm.accept(forge(&frags[1], total)) {
+                Err(ReassemblyError::OutOfSequence { id: 6, seq: 1 }) => (),
+                other => panic!("unexpected {:?}", other),
+            }
+            assert_eq!(rsm.buffered(), 0);
+            assert!(rsm.accept(frags[2].clone()).unwrap().is_none());
+            let next = Message::create_msg_str_fit("after");
+            assert!(rsm.accept(next).unwrap().is_some());
+        }
+    }
+    #[test]
+    fn small_message_not_fragmented() {
+        let msg = Message::create_msg_str_fit("tiny");
+        assert!(!needs_fragmenting(&msg, 4096));
+    }
+}
@@ -0,0 +1,226 @@
+/////////////////////////////////////////////////////////////
+// rust_comm_processing::handshake.rs - HELLO exchange     //
+//                                                         //
+// Jim Fawcett, https://JimFawcett.github.io, 18 Oct 2026  //
+/////////////////////////////////////////////////////////////
+/*
+   Hello:
+   - first thing written by both ends of a new connection
+   - does not use the Message header, so a peer built with
+     a different header layout is detected instead of
+     silently misparsing frames
+   - wire layout, all integers big-endian:
+       magic        4 bytes  "RCOM"
+       version      u16      PROTOCOL_VERSION
+       features     u32      FEATURE_* bits
+       max frame    u64      largest frame peer will accept
+       name len     u16
+       app name     name len bytes of utf-8
+*/
+
+use rust_message::PROTOCOL_VERSION;
+
+use std::fmt;
+use std::io::{Read, Write, Error, ErrorKind};
+use std::time::Duration;
+
+pub const HELLO_MAGIC: [u8; 4] = *b"RCOM";
+
+/*-- feature bits advertised in Hello::features --*/
+pub const FEATURE_COMPRESSION: u32 = 1;
+pub const FEATURE_CHECKSUM: u32 = 2;
+
+/*-- features this build knows how to use --*/
+pub const SUPPORTED_FEATURES: u32 = FEATURE_COMPRESSION | FEATURE_CHECKSUM;
+pub const DEFAULT_MAX_FRAME_SIZE: u64 = 1 << 30;
+
+/*-- longest either end waits for the other's Hello --*/
+pub const HELLO_TIMEOUT: Duration = Duration::from_secs(5);
+
+/*---------------------------------------------------------
+  HandshakeError - reason a connection was refused
+  - carried inside std::io::Error with ErrorKind::InvalidData
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum HandshakeError {
+    BadMagic([u8; 4]),
+    VersionMismatch { local: u16, peer: u16, peer_name: String },
+}
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandshakeError::BadMagic(m) => write!(
+                f, "peer is not a rust_comm endpoint, hello magic {:?}", m
+            ),
+            HandshakeError::VersionMismatch { local, peer, peer_name } => write!(
+                f, "protocol version mismatch: local v{}, peer {:?} v{}",
+                local, peer_name, peer
+            ),
+        }
+    }
+}
+impl std::error::Error for HandshakeError {}
+
+impl From<HandshakeError> for Error {
+    fn from(e: HandshakeError) -> Error {
+        Error::new(ErrorKind::InvalidData, e)
+    }
+}
+
+/*---------------------------------------------------------
+  Hello - what one endpoint tells the other about itself
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hello {
+    pub version: u16,
+    pub features: u32,
+    pub max_frame_size: u64,
+    pub app_name: String,
+}
+impl Default for Hello {
+    fn default() -> Hello {
+        Hello::new("rust_comm")
+    }
+}
+impl Hello {
+    /*-- this build's version and features --*/
+    pub fn new(app_name: &str) -> Hello {
+        Hello {
+            version: PROTOCOL_VERSION,
+            features: SUPPORTED_FEATURES,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            app_name: app_name.to_string(),
+        }
+    }
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let name = self.app_name.as_bytes();
+        let name_len = name.len().min(u16::MAX as usize);
+        let mut buf = Vec::with_capacity(20 + name_len);
+        buf.extend_from_slice(&HELLO_MAGIC);
+        buf.extend_from_slice(&self.version.to_be_bytes());
+        buf.extend_from_slice(&self.features.to_be_bytes());
+        buf.extend_from_slice(&self.max_frame_size.to_be_bytes());
+        buf.extend_from_slice(&(name_len as u16).to_be_bytes());
+        buf.extend_from_slice(&name[..name_len]);
+        buf
+    }
+    /*-- writes and flushes, peer is waiting for it --*/
+    pub fn write_to<W: Write>(&self, stream: &mut W) -> std::io::Result<()> {
+        stream.write_all(&self.to_bytes())?;
+        stream.flush()
+    }
+    pub fn read_from<R: Read>(stream: &mut R) -> std::io::Result<Hello> {
+        let mut magic = [0u8; 4];
+        stream.read_exact(&mut magic)?;
+        if magic != HELLO_MAGIC {
+            return Err(HandshakeError::BadMagic(magic).into());
+        }
+        let mut fixed = [0u8; 16];
+        stream.read_exact(&mut fixed)?;
+        let version = u16::from_be_bytes([fixed[0], fixed[1]]);
+        let mut features = [0u8; 4];
+        features.copy_from_slice(&fixed[2..6]);
+        let mut max_frame = [0u8; 8];
+        max_frame.copy_from_slice(&fixed[6..14]);
+        let name_len = u16::from_be_bytes([fixed[14], fixed[15]]) as usize;
+        let mut name = vec![0u8; name_len];
+        stream.read_exact(&mut name)?;
+        Ok(Hello {
+            version,
+            features: u32::from_be_bytes(features),
+            max_frame_size: u64::from_be_bytes(max_frame),
+            app_name: String::from_utf8_lossy(&name).into_owned(),
+        })
+    }
+    /*-- settings both ends agree to use for this connection --*/
+    pub fn negotiate(&self, peer: &Hello) -> std::result::Result<Negotiated, HandshakeError> {
+        if self.version != peer.version {
+            return Err(HandshakeError::VersionMismatch {
+                local: self.version,
+                peer: peer.version,
+                peer_name: peer.app_name.clone(),
+            });
+        }
+        Ok(Negotiated {
+            features: self.features & peer.features,
+            max_frame_size: self.max_frame_size.min(peer.max_frame_size),
+            peer_name: peer.app_name.clone(),
+        })
+    }
+}
+
+/*---------------------------------------------------------
+  Negotiated - result of a successful Hello exchange
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Negotiated {
+    pub features: u32,
+    pub max_frame_size: u64,
+    pub peer_name: String,
+}
+impl Negotiated {
+    pub fn has_feature(&self, feature: u32) -> bool {
+        self.features & feature == feature
+    }
+}
+
+/*---------------------------------------------------------
+  Connecting side speaks first, accepting side answers.
+  The acceptor always answers, even when it refuses, so
+  the connector can report the peer's version.
+*/
+pub fn client_handshake<R: Read, W: Write>(
+    local: &Hello, reader: &mut R, writer: &mut W
+) -> std::io::Result<Negotiated> {
+    local.write_to(writer)?;
+    let peer = Hello::read_from(reader)?;
+    Ok(local.negotiate(&peer)?)
+}
+pub fn server_handshake<R: Read, W: Write>(
+    local: &Hello, reader: &mut R, writer: &mut W
+) -> std::io::Result<Negotiated> {
+    let peer = Hello::read_from(reader)?;
+    local.write_to(writer)?;
+    Ok(local.negotiate(&peer)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn hello_round_trip() {
+        let mut hello = Hello::new("test app");
+        hello.features = FEATURE_CHECKSUM;
+        let bytes = hello.to_bytes();
+        let back = Hello::read_from(&mut &bytes[..]).unwrap();
+        assert_eq!(back, hello);
+    }
+    #[test]
+    fn hello_bad_magic() {
+        let mut bytes = Hello::default().to_bytes();
+        bytes[0] = 0;
+        let err = Hello::read_from(&mut &bytes[..]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+    #[test]
+    fn negotiate_features_and_frame_size() {
+        let mut a = Hello::new("a");
+        a.features = FEATURE_CHECKSUM | FEATURE_COMPRESSION;
+        a.max_frame_size = 4096;
+        let mut b = Hello::new("b");
+        b.features = FEATURE_CHECKSUM;
+        let n = a.negotiate(&b).unwrap();
+        assert!(n.has_feature(FEATURE_CHECKSUM));
+        assert!(!n.has_feature(FEATURE_COMPRESSION));
+        assert_eq!(n.max_frame_size, 4096);
+        assert_eq!(n.peer_name, "b");
+    }
+    #[test]
+    fn negotiate_version_mismatch() {
+        let a = Hello::new("a");
+        let mut b = Hello::new("b");
+        b.version = a.version + 1;
+        let err = a.negotiate(&b).unwrap_err();
+        assert!(err.to_string().contains("protocol version mismatch"));
+    }
+}
@@ -8,11 +8,30 @@
    - defines send_message, recv_message, and process_message
    - each of these needs to be tailored to the specifics of
      the Message class
+   - frames with FLAG_CHECKSUM carry a CRC32C trailer, checked
+     on receive, mismatches return a ChecksumMismatch error
+   - compress_message replaces large bodies with an lz4 block
+     and sets FLAG_COMPRESSED, receive inflates them again, so
+     processing code never sees compressed bodies
+   - buf_send_batch writes a batch of frames with one vectored
+     write where the platform allows
+   - received bodies come from global_pool() and go back to
+     it when the message is dropped, see pool.rs
+   - recv_message_limited refuses frames larger than the
+     max frame size agreed in the Hello exchange before
+     reading their bodies
 */
 
 #![allow(unused_imports)]
 #![allow(dead_code)]
 
+mod handshake;
+pub use handshake::*;
+mod fragment;
+pub use fragment::*;
+mod pool;
+pub use pool::*;
+
 /*-- RustComm facilities --*/
 use rust_traits::*;
 use rust_message::*;
@@ -23,7 +42,7 @@ use rust_comm_logger::*;
 use std::fmt::*;
 use std::net::{TcpStream};
 use std::io::prelude::*;
-use std::io::{BufReader, BufWriter, Write};
+use std::io::{BufReader, BufWriter, Write, IoSlice};
 use std::convert::{TryInto};
 
 type M = Message;
@@ -48,6 +67,202 @@ where L: Logger + Debug + Copy + Clone + Default
         }
     }
 }
+/*---------------------------------------------------------
+  ChecksumMismatch - frame failed CRC32C verification
+  - carried inside std::io::Error with ErrorKind::InvalidData
+  - stream can't be trusted afterwards, caller should close
+*/
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ChecksumMismatch {
+    pub expected: u32,
+    pub actual: u32,
+}
+impl Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(
+            f, "frame checksum mismatch: expected {:#010x}, computed {:#010x}",
+            self.expected, self.actual
+        )
+    }
+}
+impl std::error::Error for ChecksumMismatch {}
+
+pub fn is_checksum_mismatch(err: &std::io::Error) -> bool {
+    err.get_ref()
+       .map(|e| e.is::<ChecksumMismatch>())
+       .unwrap_or(false)
+}
+/*---------------------------------------------------------
+  Compression
+  - bodies smaller than COMPRESSION_THRESHOLD aren't worth it
+  - body is left alone if lz4 doesn't make it smaller
+*/
+pub const COMPRESSION_THRESHOLD: usize = 1024;
+
+pub fn compress_message<M: Msg>(msg: &mut M) {
+    let flags = msg.get_flags();
+    if flags & FLAG_COMPRESSED != 0 
+        || msg.get_content_size() < COMPRESSION_THRESHOLD 
+    {
+        return;
+    }
+    let packed = lz4_flex::compress_prepend_size(msg.get_content_bytes());
+    if packed.len() >= msg.get_content_size() {
+        return;
+    }
+    msg.set_flags(flags | FLAG_COMPRESSED);
+    msg.set_body(Bytes::from(packed));
+}
+/*-- size prefix, u32 little-endian, is checked before allocating for it --*/
+fn decompress_body(bdy: &[u8], max_frame: u64) -> std::io::Result<Vec<u8>> {
+    let invalid = |text: String| std::io::Error::new(std::io::ErrorKind::InvalidData, text);
+    let (prefix, packed) = match bdy.get(..4) {
+        Some(prefix) => (prefix, &bdy[4..]),
+        None => return Err(invalid("compressed body has no size prefix".to_string())),
+    };
+    let size = u32::from_le_bytes(prefix.try_into().unwrap());
+    if u64::from(size) > max_frame {
+        return Err(invalid(format!(
+            "compressed body inflates to {} bytes, over max frame size {}", size, max_frame
+        )));
+    }
+    lz4_flex::decompress(packed, size as usize).map_err(|e| invalid(e.to_string()))
+}
+/*---------------------------------------------------------
+  Frame on the wire:
+    header | crc32c(header) | key/value headers | content
+      | crc32c(all before)
+  key/value section is empty unless the message has some,
+  both checksums are present only if FLAG_CHECKSUM set.
+  The header's own checksum is checked before its lengths
+  are trusted, so a corrupted length is caught before the
+  receiver allocates or waits for that many bytes.
+*/
+fn write_frame<M: Msg, W: Write>(msg: &M, stream: &mut W) -> std::io::Result<()> {
+    write_frames(std::slice::from_ref(msg), stream)
+}
+/*-- header, body, and trailer of every msg in one gather list --*/
+fn write_frames<M: Msg, W: Write>(msgs: &[M], stream: &mut W) -> std::io::Result<()> {
+    let metas: Vec<Bytes> = msgs.iter().map(|msg| msg.get_meta()).collect();
+    let checksums: Vec<([u8; 4], [u8; 4])> = msgs.iter().zip(&metas).map(|(msg, meta)| {
+        if msg.get_flags() & FLAG_CHECKSUM == 0 {
+            return ([0u8; 4], [0u8; 4]);
+        }
+        let hdr_crc = crc32c::crc32c(msg.get_header_bytes());
+        let crc = crc32c::crc32c_append(hdr_crc, meta);
+        let crc = crc32c::crc32c_append(crc, msg.get_content_bytes());
+        (hdr_crc.to_be_bytes(), crc.to_be_bytes())
+    }).collect();
+    let mut slices = Vec::<IoSlice>::with_capacity(5 * msgs.len());
+    for ((msg, meta), (hdr_crc, trailer)) in msgs.iter().zip(&metas).zip(&checksums) {
+        slices.push(IoSlice::new(msg.get_header_bytes()));
+        if msg.get_flags() & FLAG_CHECKSUM != 0 {
+            slices.push(IoSlice::new(hdr_crc));
+        }
+        if !meta.is_empty() {
+            slices.push(IoSlice::new(meta));
+        }
+        if msg.get_content_size() > 0 {
+            slices.push(IoSlice::new(msg.get_content_bytes()));
+        }
+        if msg.get_flags() & FLAG_CHECKSUM != 0 {
+            slices.push(IoSlice::new(trailer));
+        }
+    }
+    write_all_vectored(stream, &mut slices)
+}
+fn write_all_vectored<W: Write>(stream: &mut W, mut bufs: &mut [IoSlice]) -> std::io::Result<()> {
+    while !bufs.is_empty() {
+        match stream.write_vectored(bufs) {
+            Ok(0) => return Err(std::io::ErrorKind::WriteZero.into()),
+            Ok(n) => IoSlice::advance_slices(&mut bufs, n),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+/*-- key/value headers and content together may not exceed max_frame --*/
+fn read_frame<M: Msg, R: Read>(stream: &mut R, max_frame: u64) -> std::io::Result<M> {
+    let buf = &mut [0u8; HEADER_SIZE];
+    stream.read_exact(buf)?;
+    let msgtype = buf[0];
+    let flags = buf[FLAGS_OFFSET];
+    if flags & FLAG_CHECKSUM != 0 {
+        check_crc(stream, crc32c::crc32c(buf))?;
+    }
+    let sz_slice = &buf[CONTENT_OFFSET..HEADER_SIZE];
+    let mut dst = [0u8;8];
+    dst.clone_from_slice(sz_slice); // array from byte slice
+    let bdysz = u64::from_be_bytes(dst);   // u64 from byte array
+    let mut meta_sz = [0u8;4];
+    meta_sz.copy_from_slice(&buf[META_OFFSET..CONTENT_OFFSET]);
+    let meta_sz = u32::from_be_bytes(meta_sz) as usize;
+    if meta_sz > MAX_META_SIZE {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData, "message headers too large"
+        ));
+    }
+    /*-- checked before allocating, the sizes come straight off the wire --*/
+    let bdysz = match bdysz.checked_add(meta_sz as u64) {
+        Some(sz) if sz <= max_frame => bdysz as usize,
+        _ => return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("frame of {} bytes exceeds max frame size {}", bdysz, max_frame)
+        )),
+    };
+    let mut meta = vec![0u8; meta_sz];
+    stream.read_exact(&mut meta)?;
+
+    /*-- body is read once, into the buffer the message keeps --*/
+    let pool = global_pool();
+    let mut bdy = pool.take(bdysz);
+    if let Err(e) = read_body(stream, buf, &meta, &mut bdy) {
+        pool.give(bdy);
+        return Err(e);
+    }
+    if flags & FLAG_COMPRESSED != 0 {
+        let wire = std::mem::take(&mut bdy);
+        let inflated = decompress_body(&wire, max_frame);
+        pool.give(wire);
+        bdy = inflated?;
+    }
+    let mut msg = M::new(HEADER_SIZE);
+    msg.set_type(msgtype);
+    msg.set_priority(buf[PRIORITY_OFFSET]);
+    let mut field = [0u8;8];
+    field.copy_from_slice(&buf[DEADLINE_OFFSET..REQUEST_ID_OFFSET]);
+    msg.set_deadline(u64::from_be_bytes(field));
+    field.copy_from_slice(&buf[REQUEST_ID_OFFSET..META_OFFSET]);
+    msg.set_request_id(u64::from_be_bytes(field));
+    msg.set_meta(Bytes::from(meta));
+    /*-- checksum and compression describe the wire frame, not the message --*/
+    msg.set_flags(flags & !(FLAG_CHECKSUM | FLAG_COMPRESSED));
+    msg.set_body(pool.freeze(bdy));
+    Ok(msg)
+}
+/*-- fills bdy, checks trailer if header asks for one, then key/value headers --*/
+fn read_body<R: Read>(
+    stream: &mut R, hdr: &[u8], meta: &[u8], bdy: &mut [u8]
+) -> std::io::Result<()> {
+    stream.read_exact(bdy)?;
+    if hdr[FLAGS_OFFSET] & FLAG_CHECKSUM != 0 {
+        let actual = crc32c::crc32c_append(crc32c::crc32c(hdr), meta);
+        check_crc(stream, crc32c::crc32c_append(actual, bdy))?;
+    }
+    check_meta(meta)
+}
+/*-- reads a checksum from stream, error if it isn't actual --*/
+fn check_crc<R: Read>(stream: &mut R, actual: u32) -> std::io::Result<()> {
+    let mut crc = [0u8; 4];
+    stream.read_exact(&mut crc)?;
+    let expected = u32::from_be_bytes(crc);
+    if expected != actual {
+        let err = ChecksumMismatch { expected, actual };
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, err));
+    }
+    Ok(())
+}
 impl<M,L> Sndr<M> for CommProcessing<L>
 where 
     M: Msg + Clone + Send + Default,
@@ -56,23 +271,29 @@ where
     fn send_message(msg: &M, stream: &mut TcpStream) -> std::io::Result<()>
     {
         L::write(&format!("\n  msg.len(): {}", msg.len()));
-        stream.write(&msg.get_ref())?;
-        Ok(())
+        write_frame(msg, stream)
     }
     fn buf_send_message(msg: &M, stream: &mut BufWriter<TcpStream>) -> std::io::Result<()>
     {
         L::write(&format!("\n  msg.len(): {}", msg.len()));
-        stream.write(&msg.get_ref())?;
+        write_frame(msg, stream)?;
         let msg_type = msg.get_type(); 
         if msg_type == MessageType::FLUSH as u8 
             || msg_type == MessageType::END as u8 
             || msg_type == MessageType::QUIT as u8 
+            || msg_type == MessageType::FILE_BEGIN as u8 
+            || msg_type == MessageType::FILE_END as u8 
         {
             L::write("\n  flushing stream");
             let _ = stream.flush();
         }
         Ok(())
     }
+    fn buf_send_batch(msgs: &[M], stream: &mut BufWriter<TcpStream>) -> std::io::Result<()>
+    {
+        L::write(&format!("\n  sending batch of {} msgs", msgs.len()));
+        write_frames(msgs, stream)
+    }
 }
 impl<M,L> Rcvr<M> for CommProcessing<L>
 where 
@@ -82,42 +303,24 @@ where
     /*-- reads message and enques in supplied BlockingQueue<M> --*/
     fn recv_message(stream: &mut TcpStream) -> std::io::Result<M> 
     {
-        L::write("\n  attempting to receive msg in commProc");
-        let buf = &mut [0u8; HEADER_SIZE];
-        stream.read_exact(buf)?;
-        let msgtype = buf[0];
-        let sz_slice = &buf[1..HEADER_SIZE];
-        let mut dst = [0u8;8];
-        dst.clone_from_slice(sz_slice); // array from byte slice
-        let bdysz = usize::from_be_bytes(dst);   // usize from byte array
-
-        let mut bdy = vec![0u8;bdysz];
-        stream.read_exact(&mut bdy)?;        
-        let msg_size = TYPE_SIZE + CONTENT_SIZE + bdysz;
-        let mut msg = M::new(msg_size);
-        msg.set_type(msgtype);
-        msg.set_content_bytes(&bdy);
-        Ok(msg)
+        Self::recv_message_limited(stream, DEFAULT_MAX_FRAME_SIZE)
     }
     /*-- same as above but uses buffered reader --*/
     fn buf_recv_message(stream: &mut BufReader<TcpStream>) -> std::io::Result<M> 
+    {
+        Self::buf_recv_message_limited(stream, DEFAULT_MAX_FRAME_SIZE)
+    }
+    fn recv_message_limited(stream: &mut TcpStream, max_frame: u64) -> std::io::Result<M> 
     {
         L::write("\n  attempting to receive msg in commProc");
-        let buf = &mut [0u8; HEADER_SIZE];
-        stream.read_exact(buf)?;
-        let msgtype = buf[0];
-        let sz_slice = &buf[1..HEADER_SIZE];
-        let mut dst = [0u8;8];
-        dst.clone_from_slice(sz_slice); // array from byte slice
-        let bdysz = usize::from_be_bytes(dst);   // usize from byte array
-
-        let mut bdy = vec![0u8;bdysz];
-        stream.read_exact(&mut bdy)?;        
-        let msg_size = TYPE_SIZE + CONTENT_SIZE + bdysz;
-        let mut msg = M::new(msg_size);
-        msg.set_type(msgtype);
-        msg.set_content_bytes(&bdy);
-        Ok(msg)
+        read_frame(stream, max_frame)
+    }
+    fn buf_recv_message_limited(
+        stream: &mut BufReader<TcpStream>, max_frame: u64
+    ) -> std::io::Result<M> 
+    {
+        L::write("\n  attempting to receive msg in commProc");
+        read_frame(stream, max_frame)
     }
 }
 /*---------------------------------------------------------
@@ -154,4 +357,164 @@ mod tests {
         let _ = CommProcessing::<MuteLog>::send_message(&msg, &mut stream);
         assert_eq!(2 + 2, 4);
     }
+    /*-- connected pair of streams on an ephemeral port --*/
+    fn stream_pair() -> (TcpStream, TcpStream) {
+        let lsnr = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(lsnr.local_addr().unwrap()).unwrap();
+        let (server, _) = lsnr.accept().unwrap();
+        (client, server)
+    }
+    #[test]
+    fn checksum_verified() {
+        let (mut client, mut server) = stream_pair();
+        let mut msg = Message::create_msg_str_fit("checked");
+        msg.set_flags(FLAG_JSON | FLAG_CHECKSUM);
+        msg.set_priority(PRIORITY_HIGH);
+        msg.set_deadline(1_700_000_000_000);
+        msg.set_request_id(7);
+        msg.set_header("trace-id", "4bf92f35").unwrap();
+        CommProcessing::<MuteLog>::send_message(&msg, &mut client).unwrap();
+        let rcvd:Message = CommProcessing::<MuteLog>::recv_message(&mut server).unwrap();
+        assert_eq!(rcvd.get_content_str().unwrap(), "checked");
+        assert_eq!(rcvd.get_flags(), FLAG_JSON);
+        assert_eq!(rcvd.get_priority(), PRIORITY_HIGH);
+        assert_eq!(rcvd.get_deadline(), 1_700_000_000_000);
+        assert_eq!(rcvd.get_request_id(), 7);
+        assert_eq!(rcvd.get_header("trace-id"), Some("4bf92f35"));
+    }
+    #[test]
+    fn checksum_mismatch_detected() {
+        let (mut client, mut server) = stream_pair();
+        let mut msg = Message::create_msg_str_fit("corrupted");
+        msg.set_flags(FLAG_CHECKSUM);
+        let mut frame = Vec::<u8>::new();
+        write_frame(&msg, &mut frame).unwrap();
+        assert_eq!(frame.len(), msg.len() + 8);
+        frame[HEADER_SIZE + 4] ^= 0x20;
+        client.write_all(&frame).unwrap();
+        let err = <CommProcessing<MuteLog> as Rcvr<Message>>::recv_message(&mut server)
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(is_checksum_mismatch(&err));
+    }
+    #[test]
+    fn corrupted_length_caught_before_body() {
+        let mut msg = Message::create_msg_str_fit("short");
+        msg.set_flags(FLAG_CHECKSUM);
+        let mut frame = Vec::<u8>::new();
+        write_frame(&msg, &mut frame).unwrap();
+        frame[CONTENT_OFFSET + 4] ^= 0x01;
+        /*-- header and its checksum only, reading the body would fail otherwise --*/
+        let err = read_frame::<Message, _>(&mut &frame[..HEADER_SIZE + 4], DEFAULT_MAX_FRAME_SIZE)
+            .unwrap_err();
+        assert!(is_checksum_mismatch(&err));
+    }
+    #[test]
+    fn checksum_covers_key_value_headers() {
+        let (mut client, mut server) = stream_pair();
+        let mut msg = Message::create_msg_str_fit("body");
+        msg.set_header("auth", "token").unwrap();
+        msg.set_flags(FLAG_CHECKSUM);
+        let mut frame = Vec::<u8>::new();
+        write_frame(&msg, &mut frame).unwrap();
+        assert_eq!(frame.len(), msg.len() + 8);
+        frame[HEADER_SIZE + 6] ^= 0x20;
+        client.write_all(&frame).unwrap();
+        let err = <CommProcessing<MuteLog> as Rcvr<Message>>::recv_message(&mut server)
+            .unwrap_err();
+        assert!(is_checksum_mismatch(&err));
+    }
+    #[test]
+    fn oversized_frame_rejected() {
+        let msg = Message::create_msg_str_fit(&"x".repeat(100));
+        let mut frame = Vec::<u8>::new();
+        write_frame(&msg, &mut frame).unwrap();
+        assert!(read_frame::<Message, _>(&mut &frame[..], 100).is_ok());
+        let err = read_frame::<Message, _>(&mut &frame[..], 99).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        /*-- huge length is refused without waiting for its body --*/
+        frame[CONTENT_OFFSET..HEADER_SIZE].copy_from_slice(&u64::MAX.to_be_bytes());
+        let err = read_frame::<Message, _>(&mut &frame[..HEADER_SIZE], 1 << 20).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+    #[test]
+    fn fragments_with_headers_fit_small_max_frame() {
+        let max_frame = 512;
+        let fragment_size = fragment_size_for(DEFAULT_FRAGMENT_SIZE, max_frame);
+        let content: Vec<u8> = (0..fragment_size - 10).map(|i| i as u8).collect();
+        let mut msg = Message::create_msg_bytes_fit(&content);
+        msg.set_header("trace-id", &"t".repeat(100)).unwrap();
+        assert!(needs_fragmenting(&msg, fragment_size));
+
+        let mut rsm = Reassembler::<Message>::default();
+        let mut whole = None;
+        for frag in Fragmenter::new(msg, 1, fragment_size) {
+            let mut frame = Vec::<u8>::new();
+            write_frame(&frag, &mut frame).unwrap();
+            let rcvd = read_frame::<Message, _>(&mut &frame[..], max_frame).unwrap();
+            whole = rsm.accept(rcvd).unwrap();
+        }
+        let whole = whole.unwrap();
+        assert_eq!(whole.get_header("trace-id"), Some(&*"t".repeat(100)));
+        assert_eq!(whole.get_content_bytes(), &content[..]);
+    }
+    #[test]
+    fn compressed_round_trip() {
+        let (mut client, mut server) = stream_pair();
+        let body = "compressible ".repeat(1000);
+        let mut msg = Message::create_msg_str_fit(&body);
+        msg.set_flags(FLAG_JSON | FLAG_CHECKSUM);
+        compress_message(&mut msg);
+        assert_ne!(msg.get_flags() & FLAG_COMPRESSED, 0);
+        assert!(msg.get_content_size() < body.len());
+        CommProcessing::<MuteLog>::send_message(&msg, &mut client).unwrap();
+        let rcvd:Message = CommProcessing::<MuteLog>::recv_message(&mut server).unwrap();
+        assert_eq!(rcvd.get_flags(), FLAG_JSON);
+        assert_eq!(rcvd.get_content_str().unwrap(), body);
+    }
+    #[test]
+    fn inflated_size_bounded_by_max_frame() {
+        let body = "compressible ".repeat(1000);
+        let mut msg = Message::create_msg_str_fit(&body);
+        compress_message(&mut msg);
+        let mut frame = Vec::<u8>::new();
+        write_frame(&msg, &mut frame).unwrap();
+        let limit = msg.get_content_size() as u64 + 100;
+        let err = read_frame::<Message, _>(&mut &frame[..], limit).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        /*-- forged prefix is refused, not allocated --*/
+        frame[HEADER_SIZE..HEADER_SIZE + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let err = read_frame::<Message, _>(&mut &frame[..], 1 << 20).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("inflates"));
+    }
+    #[test]
+    fn small_message_not_compressed() {
+        let mut msg = Message::create_msg_str_fit("short");
+        compress_message(&mut msg);
+        assert_eq!(msg.get_flags() & FLAG_COMPRESSED, 0);
+        assert_eq!(msg.get_content_str().unwrap(), "short");
+    }
+    #[test]
+    fn batch_round_trip() {
+        let (client, mut server) = stream_pair();
+        let mut batch = Vec::<Message>::new();
+        for i in 0..10 {
+            let mut msg = Message::create_msg_str_fit(&format!("msg {}", i));
+            if i % 2 == 0 {
+                msg.set_flags(FLAG_CHECKSUM);
+            }
+            batch.push(msg);
+        }
+        batch.push(Message::create_msg_header_only());
+        let mut writer = BufWriter::new(client);
+        CommProcessing::<MuteLog>::buf_send_batch(&batch, &mut writer).unwrap();
+        writer.flush().unwrap();
+        for i in 0..10 {
+            let rcvd:Message = CommProcessing::<MuteLog>::recv_message(&mut server).unwrap();
+            assert_eq!(rcvd.get_content_str().unwrap(), format!("msg {}", i));
+        }
+        let rcvd:Message = CommProcessing::<MuteLog>::recv_message(&mut server).unwrap();
+        assert_eq!(rcvd.get_content_size(), 0);
+    }
 }
@@ -0,0 +1,241 @@
+/////////////////////////////////////////////////////////////
+// rust_comm_processing::pool.rs - reuse receive buffers   //
+//                                                         //
+// Jim Fawcett, https://JimFawcett.github.io, 18 Oct 2026  //
+/////////////////////////////////////////////////////////////
+/*
+   BufferPool:
+   - keeps freed body buffers in power-of-two size classes,
+     from POOL_MIN_SIZE to POOL_MAX_SIZE
+   - freeze wraps a buffer in Bytes that hands it back to the
+     pool when the last message sharing it is dropped
+   - bodies outside the size classes, or arriving while the
+     pool is disabled, are allocated as before
+   - holds at most `limit` bytes of idle buffers
+
+   Rcvr implementations in this crate draw from global_pool().
+*/
+
+use rust_traits::Bytes;
+
+use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+
+pub const POOL_MIN_SIZE: usize = 1024;
+pub const POOL_MAX_SIZE: usize = 16 * 1024 * 1024;
+pub const DEFAULT_POOL_LIMIT: usize = 64 * 1024 * 1024;
+
+const MIN_SHIFT: u32 = POOL_MIN_SIZE.trailing_zeros();
+const NUM_CLASSES: usize = (POOL_MAX_SIZE.trailing_zeros() - MIN_SHIFT + 1) as usize;
+
+/*-- class whose buffers hold at least len bytes --*/
+fn class_for_len(len: usize) -> Option<usize> {
+    if !(POOL_MIN_SIZE..=POOL_MAX_SIZE).contains(&len) {
+        return None;
+    }
+    let size = len.next_power_of_two();
+    Some((size.trailing_zeros() - MIN_SHIFT) as usize)
+}
+/*-- largest class a buffer of this capacity can serve --*/
+fn class_for_capacity(cap: usize) -> Option<usize> {
+    if !(POOL_MIN_SIZE..=POOL_MAX_SIZE).contains(&cap) {
+        return None;
+    }
+    let floor = usize::BITS - 1 - cap.leading_zeros();
+    Some((floor - MIN_SHIFT) as usize)
+}
+fn class_size(class: usize) -> usize {
+    POOL_MIN_SIZE << class
+}
+
+/*---------------------------------------------------------
+  PoolStats - snapshot of pool activity
+*/
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct PoolStats {
+    pub hits: u64,         // takes served by an idle buffer
+    pub misses: u64,       // takes that had to allocate
+    pub returned: u64,     // buffers put back for reuse
+    pub discarded: u64,    // buffers freed, pool full or disabled
+    pub idle_bytes: usize, // capacity of buffers waiting in pool
+}
+impl PoolStats {
+    pub fn hit_rate(&self) -> f64 {
+        let takes = self.hits + self.misses;
+        if takes == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / takes as f64
+    }
+}
+
+#[derive(Debug)]
+pub struct BufferPool {
+    classes: Vec<Mutex<Vec<Vec<u8>>>>,
+    enabled: AtomicBool,
+    limit: usize,
+    idle: AtomicUsize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    returned: AtomicU64,
+    discarded: AtomicU64,
+}
+impl Default for BufferPool {
+    fn default() -> BufferPool {
+        BufferPool::new(DEFAULT_POOL_LIMIT)
+    }
+}
+impl BufferPool {
+    pub fn new(limit: usize) -> BufferPool {
+        BufferPool {
+            classes: (0..NUM_CLASSES).map(|_| Mutex::new(Vec::new())).collect(),
+            enabled: AtomicBool::new(true),
+            limit,
+            idle: AtomicUsize::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            returned: AtomicU64::new(0),
+            discarded: AtomicU64::new(0),
+        }
+    }
+    /*-- disabled pool allocates every buffer and keeps none --*/
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            self.clear();
+        }
+    }
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+    /*-- zeroed buffer of len bytes, reused if one is idle --*/
+    pub fn take(&self, len: usize) -> Vec<u8> {
+        let class = match class_for_len(len) {
+            Some(class) if self.is_enabled() => class,
+            _ => return vec![0u8; len],
+        };
+        let idle = self.classes[class].lock().unwrap().pop();
+        let mut buf = match idle {
+            Some(buf) => {
+                self.idle.fetch_sub(buf.capacity(), Ordering::Relaxed);
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                buf
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                Vec::with_capacity(class_size(class))
+            }
+        };
+        buf.resize(len, 0);
+        buf
+    }
+    /*-- keep buf for reuse if there's room --*/
+    pub fn give(&self, mut buf: Vec<u8>) {
+        let class = match class_for_capacity(buf.capacity()) {
+            Some(class) if self.is_enabled() => class,
+            _ => {
+                self.discarded.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        };
+        let cap = buf.capacity();
+        if self.idle.fetch_add(cap, Ordering::Relaxed) + cap > self.limit {
+            self.idle.fetch_sub(cap, Ordering::Relaxed);
+            self.discarded.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        buf.clear();
+        self.classes[class].lock().unwrap().push(buf);
+        self.returned.fetch_add(1, Ordering::Relaxed);
+    }
+    /*-- Bytes that return buf to this pool when dropped --*/
+    pub fn freeze(self: &Arc<Self>, buf: Vec<u8>) -> Bytes {
+        if !self.is_enabled() || class_for_capacity(buf.capacity()).is_none() {
+            return Bytes::from(buf);
+        }
+        Bytes::from_owner(PooledBuf { buf, pool: Arc::clone(self) })
+    }
+    /*-- frees idle buffers, counters are kept --*/
+    pub fn clear(&self) {
+        for class in &self.classes {
+            let bufs = std::mem::take(&mut *class.lock().unwrap());
+            for buf in bufs {
+                self.idle.fetch_sub(buf.capacity(), Ordering::Relaxed);
+            }
+        }
+    }
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            returned: self.returned.load(Ordering::Relaxed),
+            discarded: self.discarded.load(Ordering::Relaxed),
+            idle_bytes: self.idle.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/*-- owner of a pooled body, see Bytes::from_owner --*/
+struct PooledBuf {
+    buf: Vec<u8>,
+    pool: Arc<BufferPool>,
+}
+impl AsRef<[u8]> for PooledBuf {
+    fn as_ref(&self) -> &[u8] {
+        &self.buf
+    }
+}
+impl Drop for PooledBuf {
+    fn drop(&mut self) {
+        self.pool.give(std::mem::take(&mut self.buf));
+    }
+}
+
+/*-- pool shared by every receiver in the process --*/
+pub fn global_pool() -> &'static Arc<BufferPool> {
+    static POOL: OnceLock<Arc<BufferPool>> = OnceLock::new();
+    POOL.get_or_init(|| Arc::new(BufferPool::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn buffer_returned_when_last_clone_drops() {
+        let pool = Arc::new(BufferPool::default());
+        let buf = pool.take(5000);
+        assert_eq!(buf.len(), 5000);
+        assert_eq!(buf.capacity(), 8192);
+        let body = pool.freeze(buf);
+        let shared = body.clone();
+        drop(body);
+        assert_eq!(pool.stats().returned, 0);
+        drop(shared);
+        assert_eq!(pool.stats().returned, 1);
+        assert_eq!(pool.stats().idle_bytes, 8192);
+
+        let again = pool.take(8000);
+        assert!(again.iter().all(|&b| b == 0));
+        let stats = pool.stats();
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+        assert_eq!(stats.idle_bytes, 0);
+    }
+    #[test]
+    fn small_and_huge_bodies_bypass_pool() {
+        let pool = Arc::new(BufferPool::default());
+        drop(pool.freeze(pool.take(100)));
+        drop(pool.freeze(pool.take(POOL_MAX_SIZE + 1)));
+        assert_eq!(pool.stats(), PoolStats::default());
+    }
+    #[test]
+    fn limit_discards_extra_buffers() {
+        let pool = Arc::new(BufferPool::new(4096));
+        let a = pool.take(4096);
+        let b = pool.take(4096);
+        pool.give(a);
+        pool.give(b);
+        let stats = pool.stats();
+        assert_eq!((stats.returned, stats.discarded), (1, 1));
+        assert_eq!(stats.idle_bytes, 4096);
+    }
+}
@@ -15,11 +15,11 @@ fn main() {
 
     print!("\n  -- demo writing directly to msg buffer --\n");
     let mut msg = Message::new(MESS_SIZE);
-    print!("\n  msg len: {:?}",msg.get_ref().len());
-    for i in TYPE_SIZE + CONTENT_SIZE..MESS_SIZE {
-      msg.get_mut_ref()[i] = i as u8;
+    print!("\n  msg len: {:?}",msg.len());
+    for i in HEADER_SIZE..MESS_SIZE {
+      msg.set_field(i, &[i as u8]);
     }
-    msg.set_content_size(MESS_SIZE);
+    msg.set_content_size(MESS_SIZE - HEADER_SIZE);
     msg.show_message(8);
     println!();
 
@@ -32,8 +32,8 @@ fn main() {
     let sz = msg.get_content_size();
     print!("\n  content size: {:?}",sz);
     let rslt = msg.get_content_str();
-    if let Ok(_) = rslt {
-      print!("\n  contents: {:?}",rslt.unwrap());
+    if let Ok(s) = rslt {
+      print!("\n  contents: {:?}",s);
     }
     println!();
 
@@ -74,6 +74,15 @@ fn main() {
     print!("\n\n  msg content size: {}",sz);
     println!();
 
+    print!("\n  -- demo sharing one body between messages --\n");
+    let body = Bytes::from_static(b"shared body");
+    let first = Message::create_msg_shared(body.clone());
+    let second = first.clone();
+    print!("\n  same buffer: {}",
+      first.get_content_bytes().as_ptr() == second.get_content_bytes().as_ptr());
+    print!("\n  content: {:?}",second.get_content_str().unwrap());
+    println!();
+
     print!("\n  -- demo header only message --\n");
     let msg = Message::create_msg_header_only();
     let sz = msg.get_content_size();
@@ -0,0 +1,44 @@
+/////////////////////////////////////////////////////////////
+// rust_message::test2.rs - demonstrate typed payloads     //
+//                                                         //
+// Jim Fawcett, https://JimFawcett.github.io, 18 Oct 2026  //
+/////////////////////////////////////////////////////////////
+/*
+   cargo run --example test2 --features serde
+*/
+
+use rust_traits::*;
+use rust_message::*;
+use serde::{Serialize, Deserialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Reading {
+    sensor: String,
+    values: Vec<f64>,
+}
+
+fn main() {
+    print!("\n  -- demo typed Message payloads --\n");
+
+    let reading = Reading {
+        sensor: "thermo #3".to_string(),
+        values: vec![20.5, 21.0, 21.25],
+    };
+
+    print!("\n  -- bincode encoding --\n");
+    let msg = Message::from_typed(MessageType::TEXT as u8, &reading).unwrap();
+    msg.show_message(8);
+    print!("\n\n  encoding: {:?}", msg.encoding());
+    print!("\n  decoded:  {:?}", msg.decode::<Reading>().unwrap());
+    println!();
+
+    print!("\n  -- json encoding --\n");
+    let msg = Message::from_typed_with(
+        MessageType::TEXT as u8, &reading, Encoding::Json
+    ).unwrap();
+    print!("\n  body:     {}", msg.get_content_str().unwrap());
+    print!("\n  encoding: {:?}", msg.encoding());
+    print!("\n  decoded:  {:?}", msg.decode::<Reading>().unwrap());
+
+    print!("\n\n  That's all Folks!\n\n");
+}
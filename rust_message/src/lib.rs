@@ -5,28 +5,93 @@
 /////////////////////////////////////////////////////////////
 /*
    Message:
-   - fixed size header holding a MessageType attribute:
-     TEXT, BYTES, END, QUIT, REPLY
+   - fixed size header holding a type code, MessageType for
+     the library's codes, applications register their own,
+     see registry.rs
+   - header flags byte describes how the body is encoded
+   - header priority byte, higher values are sent first
+   - header deadline, ms since the unix epoch, 0 for none,
+     expired messages are dropped instead of sent or processed
+   - header request id, copied to replies, named by CANCEL
+   - optional key/value headers, set_header and get_header,
+     sent between fixed header and body, see meta.rs
    - body holds utf-8 text or arbitrary byte sequence
-   - stores contents in std::Vec<u8>
+   - stores header in a fixed array and body in bytes::Bytes,
+     so clones share the body instead of copying it
+   - with feature "serde", bodies can hold bincode or json
+     serialized values, see typed.rs
+   - ERROR replies carry a status code, message, and details,
+     see status.rs
 */
 
 #![allow(dead_code)]
 
+mod status;
+pub use status::*;
+mod registry;
+pub use registry::*;
+mod meta;
+pub use meta::*;
+#[cfg(feature = "serde")]
+mod typed;
+#[cfg(feature = "serde")]
+pub use typed::*;
+
 // use std::fmt::*;
 use rust_traits::*;
+use bytes::BytesMut;
 use std::str::Utf8Error;
 
 /*---------------------------------------------------------
   Message:
-  - structure that wraps Vec<u8>, treated as byte array
+  - header array and shared body, treated as one byte array
+    by offset based functions like set_field
   - set of public functions for manipulating Message state
 */
 pub const TYPE_SIZE:usize = 1;
+pub const FLAGS_SIZE:usize = 1;
+pub const PRIORITY_SIZE:usize = 1;
+pub const DEADLINE_SIZE:usize = 8;
+pub const REQUEST_ID_SIZE:usize = 8;
+pub const META_SIZE:usize = 4;
 pub const CONTENT_SIZE:usize = 8;  // max 4096 - 32 - 1 = 4063
-pub const HEADER_SIZE:usize = TYPE_SIZE + CONTENT_SIZE;
+pub const HEADER_SIZE:usize = TYPE_SIZE + FLAGS_SIZE + PRIORITY_SIZE 
+    + DEADLINE_SIZE + REQUEST_ID_SIZE + META_SIZE + CONTENT_SIZE;
+
+/*---------------------------------------------------------
+  header layout: 
+    type | flags | priority | deadline | request id | 
+    meta size | content size
+  meta size bytes of key/value headers follow, then content
+*/
+pub const FLAGS_OFFSET:usize = TYPE_SIZE;
+pub const PRIORITY_OFFSET:usize = FLAGS_OFFSET + FLAGS_SIZE;
+pub const DEADLINE_OFFSET:usize = PRIORITY_OFFSET + PRIORITY_SIZE;
+pub const REQUEST_ID_OFFSET:usize = DEADLINE_OFFSET + DEADLINE_SIZE;
+pub const META_OFFSET:usize = REQUEST_ID_OFFSET + REQUEST_ID_SIZE;
+pub const CONTENT_OFFSET:usize = META_OFFSET + META_SIZE;
+
+/*-- bump whenever the header layout changes --*/
+pub const PROTOCOL_VERSION:u16 = 6;
 
+/*-- common priorities, any u8 is allowed, higher goes first --*/
+pub const PRIORITY_NORMAL:u8 = 0;
+pub const PRIORITY_HIGH:u8 = 128;
+pub const PRIORITY_CONTROL:u8 = 255;
+
+/*-- flag bits, ENCODING_MASK bits name the body encoding --*/
+pub const FLAG_BINCODE:u8 = 1;
+pub const FLAG_JSON:u8 = 2;
+pub const ENCODING_MASK:u8 = FLAG_BINCODE | FLAG_JSON;
+/*-- frame carries CRC32C checksums of its header and of the whole frame --*/
+pub const FLAG_CHECKSUM:u8 = 4;
+/*-- body is an lz4 block, prefixed with its original size --*/
+pub const FLAG_COMPRESSED:u8 = 8;
+
+/*-- library type codes, all in LIBRARY_TYPES --*/
 #[repr(u8)]
+#[allow(non_camel_case_types)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum MessageType {
     DEFAULT = 0,
     TEXT = 1,
@@ -34,11 +99,54 @@ pub enum MessageType {
     END = 4,
     QUIT = 8,
     FLUSH = 16,
+    FRAGMENT = 32,
+    FILE_BEGIN = 33,
+    FILE_CHUNK = 34,
+    FILE_END = 35,
+    EXPIRED = 36,
+    CANCEL = 37,
+    CANCELLED = 38,
+    ERROR = 39,
+}
+impl MessageType {
+    pub const ALL: [MessageType; 14] = [
+        MessageType::DEFAULT, MessageType::TEXT, MessageType::REPLY,
+        MessageType::END, MessageType::QUIT, MessageType::FLUSH,
+        MessageType::FRAGMENT, MessageType::FILE_BEGIN, MessageType::FILE_CHUNK,
+        MessageType::FILE_END, MessageType::EXPIRED, MessageType::CANCEL,
+        MessageType::CANCELLED, MessageType::ERROR,
+    ];
+    pub fn name(self) -> &'static str {
+        match self {
+            MessageType::DEFAULT => "DEFAULT",
+            MessageType::TEXT => "TEXT",
+            MessageType::REPLY => "REPLY",
+            MessageType::END => "END",
+            MessageType::QUIT => "QUIT",
+            MessageType::FLUSH => "FLUSH",
+            MessageType::FRAGMENT => "FRAGMENT",
+            MessageType::FILE_BEGIN => "FILE_BEGIN",
+            MessageType::FILE_CHUNK => "FILE_CHUNK",
+            MessageType::FILE_END => "FILE_END",
+            MessageType::EXPIRED => "EXPIRED",
+            MessageType::CANCEL => "CANCEL",
+            MessageType::CANCELLED => "CANCELLED",
+            MessageType::ERROR => "ERROR",
+        }
+    }
+    pub fn from_code(code: u8) -> Option<MessageType> {
+        MessageType::ALL.iter().copied().find(|&mt| mt as u8 == code)
+    }
+    pub fn from_name(name: &str) -> Option<MessageType> {
+        MessageType::ALL.iter().copied().find(|mt| mt.name() == name)
+    }
 }
 
- #[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct Message {
-    br: Vec<u8>,
+    hdr: [u8; HEADER_SIZE],
+    meta: Bytes,
+    body: Bytes,
 } 
 impl Msg for Message {
     /*-------------------------------------------
@@ -47,122 +155,145 @@ impl Msg for Message {
     fn new(sz:usize) -> Self {
         assert!(sz >= HEADER_SIZE);
         Self {
-            br: vec![0; sz],
+            hdr: [0; HEADER_SIZE],
+            meta: Bytes::new(),
+            body: Bytes::from(vec![0; sz - HEADER_SIZE]),
         }
     }
-    /*-- load existing heap array with zeros --*/
+    /*-- load header and body with zeros --*/
     fn init(&mut self) {
-        let sz = self.len();
-        self.br = vec![0;sz];
+        let sz = self.body.len();
+        self.hdr = [0; HEADER_SIZE];
+        self.meta = Bytes::new();
+        self.body = Bytes::from(vec![0;sz]);
     }
     /*-- return message length --*/
     fn len(&self) -> usize {
-        self.br.len()
+        HEADER_SIZE + self.meta.len() + self.body.len()
     }
+    /*-- no body --*/
     fn is_empty(&self) -> bool {
-        self.br.len() == 0
+        self.body.is_empty()
     }
     /*-- set message MsgType --*/
     fn set_type(&mut self, mt:u8) {
-        self.br[0] = mt as u8;
+        self.hdr[0] = mt;
     }
     fn get_type(&self) -> u8 {
-        self.br[0]
+        self.hdr[0]
+    }
+    /*-- set message flags, see FLAG_* --*/
+    fn set_flags(&mut self, flags:u8) {
+        self.hdr[FLAGS_OFFSET] = flags;
+    }
+    fn get_flags(&self) -> u8 {
+        self.hdr[FLAGS_OFFSET]
+    }
+    /*-- set send priority, see PRIORITY_* --*/
+    fn set_priority(&mut self, priority:u8) {
+        self.hdr[PRIORITY_OFFSET] = priority;
+    }
+    fn get_priority(&self) -> u8 {
+        self.hdr[PRIORITY_OFFSET]
+    }
+    fn set_deadline(&mut self, deadline_ms:u64) {
+        self.hdr[DEADLINE_OFFSET..REQUEST_ID_OFFSET].copy_from_slice(&deadline_ms.to_be_bytes());
+    }
+    fn get_deadline(&self) -> u64 {
+        let mut dst = [0u8;8];
+        dst.copy_from_slice(&self.hdr[DEADLINE_OFFSET..REQUEST_ID_OFFSET]);
+        u64::from_be_bytes(dst)
+    }
+    fn set_request_id(&mut self, id:u64) {
+        self.hdr[REQUEST_ID_OFFSET..META_OFFSET].copy_from_slice(&id.to_be_bytes());
+    }
+    fn get_request_id(&self) -> u64 {
+        let mut dst = [0u8;8];
+        dst.copy_from_slice(&self.hdr[REQUEST_ID_OFFSET..META_OFFSET]);
+        u64::from_be_bytes(dst)
+    }
+    fn set_header(&mut self, key:&str, value:&str) -> std::io::Result<()> {
+        self.set_meta(Bytes::from(meta_with(&self.meta, key, value)?));
+        Ok(())
+    }
+    fn get_header(&self, key:&str) -> Option<&str> {
+        meta_entries(&self.meta).find(|(k, _)| *k == key).map(|(_, v)| v)
+    }
+    fn remove_header(&mut self, key:&str) -> bool {
+        if self.get_header(key).is_none() {
+            return false;
+        }
+        self.set_meta(Bytes::from(meta_without(&self.meta, key)));
+        true
+    }
+    fn set_meta(&mut self, meta: Bytes) {
+        self.hdr[META_OFFSET..CONTENT_OFFSET]
+            .copy_from_slice(&(meta.len() as u32).to_be_bytes());
+        self.meta = meta;
+    }
+    fn get_meta(&self) -> Bytes {
+        self.meta.clone()
     }
     /*-------------------------------------------
       Set message content from buff and set
       content size to length of buff
     */
     fn set_content_bytes(&mut self, buff: &[u8]) {
-        self.set_content_size(buff.len());
-        self.set_field(HEADER_SIZE, buff);
+        self.set_body(Bytes::copy_from_slice(buff));
     }
     fn get_content_bytes(&self) -> &[u8] {
-        self.get_field(
-            HEADER_SIZE, 
-            self.get_content_size()
-        )
+        let sz = self.get_content_size().min(self.body.len());
+        &self.body[..sz]
     }
     /*-------------------------------------------
       Set message content from str and set
       content size to length of str
     */
     fn set_content_str(&mut self, s:&str) {
-        self.set_content_size(s.len());
         self.set_content_bytes(s.as_bytes());
     }
     fn get_content_str(&self) ->Result<&str, Utf8Error> {
-        let sz = self.get_content_size();
-        let start = HEADER_SIZE;
-        let end = start + sz;
-        Self::str_from_bytes(&self.br[start..end])
+        Self::str_from_bytes(self.get_content_bytes())
     }
     /*-- set message content size --*/
     fn set_content_size(&mut self, sz:usize) {
-        self.set_field(TYPE_SIZE, &sz.to_be_bytes());
+        self.hdr[CONTENT_OFFSET..HEADER_SIZE].copy_from_slice(&sz.to_be_bytes());
     }
     fn get_content_size(&self) -> usize {
-        let bytes = self.get_field(TYPE_SIZE, CONTENT_SIZE);
+        let bytes = &self.hdr[CONTENT_OFFSET..HEADER_SIZE];
         let mut dst = [0u8;8];
         dst.clone_from_slice(bytes); // array from byte slice
         usize::from_be_bytes(dst)    // usize from byte array
     }
-    fn get_bytes(&self) -> &[u8] {
-        &self.br[..]
-    }
-    fn get_mut_bytes(&mut self) -> &mut [u8] {
-        &mut self.br[..]
-    }
-    fn set_bytes(&mut self, buff:&[u8]) {
-        for i in 0..buff.len() {
-            self.br[i] = buff[i];
-        }
+    fn get_header_bytes(&self) -> &[u8] {
+        &self.hdr[..]
     }
-    fn get_ref(&self) -> &Vec<u8> {
-        &self.br
+    fn set_body(&mut self, body: Bytes) {
+        self.set_content_size(body.len());
+        self.body = body;
     }
-    fn get_mut_ref(&mut self) -> &mut Vec<u8> {
-        &mut self.br
+    fn get_body(&self) -> Bytes {
+        let sz = self.get_content_size().min(self.body.len());
+        self.body.slice(..sz)
     }
     /*-------------------------------------------
       Display message with folded contents
     */
     fn show_message(&self, fold:usize) {
-        let mut foldpoint = 0;
+        let mut bytes = self.hdr.iter().chain(self.meta.iter()).chain(self.body.iter());
         loop {
             print!("\n  ");
-            for i in 0..fold {
-                if i + foldpoint < self.br.len() {
-                    print!("{:>3} ", self.br[i + foldpoint]);
-                }
-                else {
-                    return;
+            for _ in 0..fold {
+                match bytes.next() {
+                    Some(b) => print!("{:>3} ", b),
+                    None => return,
                 }
             }
-            foldpoint += fold;
         }
     }
+    /*-- see registry.rs, unknown codes show as UNKNOWN(0x..) --*/
     fn type_display(&self) -> String {
-        let mut rtn:String = String::from("UNKNOWN");
-        if self.br[0] == MessageType::DEFAULT as u8 {
-            rtn = String::from("DEFAULT");
-        }
-        else if self.br[0] == MessageType::END as u8 {
-            rtn = String::from("END");
-        }
-        else if self.br[0] == MessageType::QUIT as u8 {
-            rtn = String::from("QUIT");
-        }
-        else if self.br[0] == MessageType::REPLY as u8 {
-            rtn = String::from("REPLY");
-        }
-        else if self.br[0] == MessageType::TEXT as u8 {
-            rtn = String::from("TEXT");
-        }
-        else if self.br[0] == MessageType::FLUSH as u8 {
-            rtn = String::from("FLUSH");
-        }
-        rtn
+        display_type(self.hdr[0])
     }
 }
 impl Message {
@@ -170,23 +301,15 @@ impl Message {
       Secondary interface
     */
     pub fn create_msg_str_fit(content: &str) -> Message {
-        let msg_size = content.len() + HEADER_SIZE;
-        let mut msg = Message::new(msg_size);
-        let cnt_len = content.len();
-        msg.set_content_size(cnt_len);
-        if cnt_len > 0 {
-            msg.set_content_str(content);
-        }
-        msg
+        Self::create_msg_bytes_fit(content.as_bytes())
     }
     pub fn create_msg_bytes_fit(content: &[u8]) -> Message {
-        let msg_size = content.len() + HEADER_SIZE;
-        let mut msg = Message::new(msg_size);
-        let cnt_len = content.len();
-        msg.set_content_size(cnt_len);
-        if cnt_len > 0 {
-            msg.set_content_bytes(content);
-        }
+        Self::create_msg_shared(Bytes::copy_from_slice(content))
+    }
+    /*-- body used as is, e.g., to broadcast one buffer --*/
+    pub fn create_msg_shared(content: Bytes) -> Message {
+        let mut msg = Message::new(HEADER_SIZE);
+        msg.set_body(content);
         msg
     }
     pub fn create_msg_header_only() -> Message {
@@ -194,15 +317,56 @@ impl Message {
         msg.set_content_size(0);
         msg
     }
+    /*-- asks the listener to stop work on request_id, jumps the send queue --*/
+    pub fn create_msg_cancel(request_id:u64) -> Message {
+        let mut msg = Message::create_msg_header_only();
+        msg.set_type(MessageType::CANCEL as u8);
+        msg.set_priority(PRIORITY_CONTROL);
+        msg.set_request_id(request_id);
+        msg
+    }
+    /*-- ERROR reply, see status.rs --*/
+    pub fn create_msg_error(status:u32, message:&str, details:&[u8]) -> Message {
+        ErrorReply::new(status, message).with_details(details).to_msg()
+    }
+    /*-- makes self an ERROR reply to the request it holds --*/
+    pub fn set_error(&mut self, status:u32, message:&str) {
+        ErrorReply::new(status, message).apply_to(self);
+    }
+    /*-- contents of an ERROR reply, None for other messages --*/
+    pub fn get_error(&self) -> Option<ErrorReply> {
+        ErrorReply::from_msg(self)
+    }
+    /*-------------------------------------------------
+      offsets count from start of header, body starts at
+      HEADER_SIZE, key/value headers aren't included,
+      bytes past end are ignored
+    */
     pub fn set_field(&mut self, offset:usize, buff: &[u8]) {
         for (i, item) in buff.iter().enumerate() {
-            if i + offset < self.br.len() {
-                self.br[i + offset] = *item;
+            if i + offset < HEADER_SIZE {
+                self.hdr[i + offset] = *item;
             }
         }
+        let end = (offset + buff.len()).min(HEADER_SIZE + self.body.len());
+        if end <= HEADER_SIZE {
+            return;
+        }
+        let start = offset.max(HEADER_SIZE);
+        let src = &buff[start - offset..end - offset];
+        let mut body = self.body_mut();
+        body[start - HEADER_SIZE..end - HEADER_SIZE].copy_from_slice(src);
+        self.body = body.freeze();
     }
+    /*-- field must lie within header or within body --*/
     pub fn get_field(&self, offset:usize, size:usize) -> &[u8] {
-        &self.br[offset..offset+size]
+        if offset >= HEADER_SIZE {
+            let start = offset - HEADER_SIZE;
+            &self.body[start..start+size]
+        }
+        else {
+            &self.hdr[offset..offset+size]
+        }
     }
     pub fn set_str(&mut self, offset:usize, s:&str) {
         let buff = Self::str_to_bytes(s);
@@ -210,7 +374,7 @@ impl Message {
     }
     pub fn get_str(&self, offset:usize, size:usize) 
         -> Result<&str, Utf8Error> {
-        Self::str_from_bytes(&self.br[offset..offset+size])
+        Self::str_from_bytes(self.get_field(offset, size))
     }
     pub fn str_to_bytes(s:&str) -> &[u8] {
         s.as_bytes()
@@ -218,11 +382,85 @@ impl Message {
     pub fn str_from_bytes(b: &[u8]) -> Result<&str, Utf8Error> {
         std::str::from_utf8(b)
     }
+    /*-- copies body only if another message shares it --*/
+    fn body_mut(&mut self) -> BytesMut {
+        match std::mem::take(&mut self.body).try_into_mut() {
+            Ok(body) => body,
+            Err(shared) => BytesMut::from(&shared[..]),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    // use super::*;
+    use super::*;
+    #[test]
+    fn clone_shares_body() {
+        let msg = Message::create_msg_bytes_fit(&[7u8; 4096]);
+        let copy = msg.clone();
+        assert_eq!(
+            msg.get_content_bytes().as_ptr(), copy.get_content_bytes().as_ptr()
+        );
+        assert_eq!(msg.get_body().as_ptr(), msg.get_content_bytes().as_ptr());
+    }
+    #[derive(Debug)]
+    struct FixedClock(u64);
+    impl Clock for FixedClock {
+        fn now_millis(&self) -> u64 {
+            self.0
+        }
+    }
+    #[test]
+    fn ttl_sets_deadline() {
+        let mut msg = Message::create_msg_str_fit("soon stale");
+        assert!(!msg.is_expired(u64::MAX));
+        msg.set_ttl(std::time::Duration::from_millis(250), &FixedClock(1000));
+        assert_eq!(msg.get_deadline(), 1250);
+        assert!(!msg.is_expired(1249));
+        assert!(msg.is_expired(1250));
+        assert_eq!(msg.get_content_str().unwrap(), "soon stale");
+    }
+    #[test]
+    fn huge_ttl_saturates_deadline() {
+        let mut msg = Message::create_msg_str_fit("never stale");
+        msg.set_ttl(std::time::Duration::MAX, &FixedClock(1000));
+        assert_eq!(msg.get_deadline(), u64::MAX);
+        assert!(!msg.is_expired(1000));
+    }
+    #[test]
+    fn cancel_names_request() {
+        let mut msg = Message::create_msg_cancel(42);
+        msg.set_deadline(u64::MAX);
+        assert_eq!(msg.get_request_id(), 42);
+        assert_eq!(msg.get_priority(), PRIORITY_CONTROL);
+        assert_eq!(msg.type_display(), "CANCEL");
+        assert_eq!(msg.get_content_size(), 0);
+    }
+    #[test]
+    fn headers_kept_apart_from_body() {
+        let mut msg = Message::create_msg_str_fit("body");
+        msg.set_header("trace-id", "4bf92f35").unwrap();
+        msg.set_header("content-type", "text/plain").unwrap();
+        assert_eq!(msg.get_header("trace-id"), Some("4bf92f35"));
+        assert_eq!(msg.get_header("auth"), None);
+        assert_eq!(msg.get_content_str().unwrap(), "body");
+        assert_eq!(msg.len(), HEADER_SIZE + msg.get_meta().len() + 4);
+        let copy = msg.clone();
+        assert!(msg.remove_header("trace-id"));
+        assert!(!msg.remove_header("trace-id"));
+        assert_eq!(msg.get_header("trace-id"), None);
+        assert_eq!(copy.get_header("trace-id"), Some("4bf92f35"));
+        msg.set_str(HEADER_SIZE, "B");
+        assert_eq!(msg.get_content_str().unwrap(), "Body");
+    }
+    #[test]
+    fn set_field_copies_shared_body() {
+        let mut msg = Message::create_msg_str_fit("abcd");
+        let copy = msg.clone();
+        msg.set_str(HEADER_SIZE + 1, "XY");
+        assert_eq!(msg.get_content_str().unwrap(), "aXYd");
+        assert_eq!(copy.get_content_str().unwrap(), "abcd");
+    }
     #[test]
     fn construction() {
         // let mut msg = Message::new();
@@ -0,0 +1,151 @@
+/////////////////////////////////////////////////////////////
+// rust_message::meta.rs - key/value header section        //
+//                                                         //
+// Jim Fawcett, https://JimFawcett.github.io, 18 Oct 2026  //
+/////////////////////////////////////////////////////////////
+/*
+   Messages may carry string key/value pairs, e.g., tracing
+   ids, auth tokens, content type, between the fixed header
+   and the body.  Msg::set_header and Msg::get_header use
+   them, and the fixed header records the section's size.
+
+   Section layout, lengths big-endian u16:
+     key len | key utf-8 | value len | value utf-8, repeated
+   Keys are unique, and the whole section is at most
+   MAX_META_SIZE bytes.
+
+   Receivers check sections from the wire with check_meta
+   before handing them to a message.
+*/
+
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind};
+
+pub const MAX_META_SIZE:usize = 64 * 1024;
+const LEN_SIZE:usize = 2;
+
+/*-- (key, value) pairs of a well formed section, stops at the first bad entry --*/
+pub fn meta_entries(meta: &[u8]) -> MetaEntries<'_> {
+    MetaEntries { rest: meta }
+}
+#[derive(Debug, Clone)]
+pub struct MetaEntries<'a> {
+    rest: &'a [u8],
+}
+impl<'a> MetaEntries<'a> {
+    fn field(&mut self) -> Option<&'a str> {
+        if self.rest.len() < LEN_SIZE {
+            return None;
+        }
+        let len = u16::from_be_bytes([self.rest[0], self.rest[1]]) as usize;
+        let end = LEN_SIZE + len;
+        if self.rest.len() < end {
+            return None;
+        }
+        let field = std::str::from_utf8(&self.rest[LEN_SIZE..end]).ok()?;
+        self.rest = &self.rest[end..];
+        Some(field)
+    }
+}
+impl<'a> Iterator for MetaEntries<'a> {
+    type Item = (&'a str, &'a str);
+    fn next(&mut self) -> Option<(&'a str, &'a str)> {
+        let entry = self.field().and_then(|key| Some((key, self.field()?)));
+        if entry.is_none() {
+            self.rest = &[];
+        }
+        entry
+    }
+}
+
+/*-- section with key set to value, replacing any old value --*/
+/*-- InvalidInput if a field or the section gets too large --*/
+pub fn meta_with(meta: &[u8], key: &str, value: &str) -> std::io::Result<Vec<u8>> {
+    let mut out = meta_without(meta, key);
+    append_field(&mut out, key)?;
+    append_field(&mut out, value)?;
+    if out.len() > MAX_META_SIZE {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("message headers exceed MAX_META_SIZE, {} bytes", out.len())
+        ));
+    }
+    Ok(out)
+}
+/*-- section with key removed --*/
+pub fn meta_without(meta: &[u8], key: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(meta.len());
+    for (k, v) in meta_entries(meta).filter(|(k, _)| *k != key) {
+        /* read with u16 lengths, so they fit again */
+        push_field(&mut out, k);
+        push_field(&mut out, v);
+    }
+    out
+}
+fn append_field(out: &mut Vec<u8>, field: &str) -> std::io::Result<()> {
+    if u16::try_from(field.len()).is_err() {
+        return Err(Error::new(ErrorKind::InvalidInput, "message header key or value over 64K"));
+    }
+    push_field(out, field);
+    Ok(())
+}
+fn push_field(out: &mut Vec<u8>, field: &str) {
+    out.extend_from_slice(&(field.len() as u16).to_be_bytes());
+    out.extend_from_slice(field.as_bytes());
+}
+
+/*-- InvalidData unless meta is a well formed section --*/
+pub fn check_meta(meta: &[u8]) -> std::io::Result<()> {
+    if meta.len() > MAX_META_SIZE {
+        return Err(Error::new(ErrorKind::InvalidData, "message headers too large"));
+    }
+    let mut keys = HashSet::<&str>::new();
+    let mut used = 0;
+    for (key, value) in meta_entries(meta) {
+        if !keys.insert(key) {
+            return Err(Error::new(ErrorKind::InvalidData, "duplicate message header key"));
+        }
+        used += 2 * LEN_SIZE + key.len() + value.len();
+    }
+    if used != meta.len() {
+        return Err(Error::new(ErrorKind::InvalidData, "malformed message headers"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn set_replace_remove() {
+        let meta = meta_with(&[], "trace-id", "abc123").unwrap();
+        let meta = meta_with(&meta, "content-type", "application/json").unwrap();
+        let meta = meta_with(&meta, "trace-id", "def456").unwrap();
+        let entries: Vec<_> = meta_entries(&meta).collect();
+        assert_eq!(entries, [("content-type", "application/json"), ("trace-id", "def456")]);
+        assert!(check_meta(&meta).is_ok());
+        let meta = meta_without(&meta, "content-type");
+        assert_eq!(meta_entries(&meta).collect::<Vec<_>>(), [("trace-id", "def456")]);
+    }
+    #[test]
+    fn bad_sections_refused() {
+        let meta = meta_with(&[], "key", "value").unwrap();
+        assert!(check_meta(&meta[..meta.len() - 1]).is_err());
+        let mut twice = meta.clone();
+        twice.extend_from_slice(&meta);
+        assert!(check_meta(&twice).is_err());
+        assert!(check_meta(&[0, 1, 0xff, 0, 0]).is_err());
+        assert!(check_meta(&[]).is_ok());
+    }
+    #[test]
+    fn oversized_headers_refused() {
+        let long = "v".repeat(u16::MAX as usize + 1);
+        let err = meta_with(&[], "key", &long).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        let value = "v".repeat(40_000);
+        let meta = meta_with(&[], "a", &value).unwrap();
+        assert!(meta_with(&meta, "b", &value).is_err());
+        assert_eq!(meta_with(&meta, "a", "short").unwrap().len(), 2 * LEN_SIZE + 1 + 5);
+    }
+}
@@ -0,0 +1,119 @@
+/////////////////////////////////////////////////////////////
+// rust_message::registry.rs - names for message types     //
+//                                                         //
+// Jim Fawcett, https://JimFawcett.github.io, 18 Oct 2026  //
+/////////////////////////////////////////////////////////////
+/*
+   Message type codes are one header byte:
+   - LIBRARY_TYPES, 0..=127, belong to this library, named
+     by MessageType, whether assigned yet or not
+   - FIRST_USER_TYPE..=255 are for applications, which name
+     theirs with register_type, once, at startup
+   Names are unique, so type_code can route by name.  Codes
+   neither library nor registered display as UNKNOWN(0x..).
+
+   The registry is process wide, both ends of a link need
+   to register the same codes.
+*/
+
+use crate::*;
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::ops::RangeInclusive;
+use std::sync::{OnceLock, RwLock};
+
+pub const LIBRARY_TYPES: RangeInclusive<u8> = 0..=127;
+pub const FIRST_USER_TYPE: u8 = 128;
+
+fn registry() -> &'static RwLock<HashMap<u8, String>> {
+    static REGISTRY: OnceLock<RwLock<HashMap<u8, String>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/*-- registering the same code and name again is harmless --*/
+pub fn register_type(code: u8, name: &str) -> std::io::Result<()> {
+    if LIBRARY_TYPES.contains(&code) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("type code {} is reserved for the library", code),
+        ));
+    }
+    if name.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidInput, "type name is empty"));
+    }
+    let mut types = registry().write().unwrap();
+    if let Some(existing) = types.get(&code) {
+        if existing == name {
+            return Ok(());
+        }
+        return Err(Error::new(
+            ErrorKind::AlreadyExists,
+            format!("type code {} is already registered as {}", code, existing),
+        ));
+    }
+    let taken = MessageType::from_name(name).is_some()
+        || types.values().any(|existing| existing == name);
+    if taken {
+        return Err(Error::new(
+            ErrorKind::AlreadyExists,
+            format!("type name {} is already in use", name),
+        ));
+    }
+    types.insert(code, name.to_string());
+    Ok(())
+}
+/*-- library or registered name, None for unknown codes --*/
+pub fn type_name(code: u8) -> Option<String> {
+    if let Some(mt) = MessageType::from_code(code) {
+        return Some(mt.name().to_string());
+    }
+    registry().read().unwrap().get(&code).cloned()
+}
+pub fn type_code(name: &str) -> Option<u8> {
+    if let Some(mt) = MessageType::from_name(name) {
+        return Some(mt as u8);
+    }
+    registry().read().unwrap()
+        .iter()
+        .find(|(_, existing)| existing.as_str() == name)
+        .map(|(code, _)| *code)
+}
+/*-- name, or UNKNOWN(0x..) for codes nobody named --*/
+pub fn display_type(code: u8) -> String {
+    type_name(code).unwrap_or_else(|| format!("UNKNOWN(0x{:02x})", code))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn registered_types_are_named() {
+        register_type(200, "APP_QUERY").unwrap();
+        register_type(200, "APP_QUERY").unwrap();
+        assert_eq!(type_name(200).as_deref(), Some("APP_QUERY"));
+        assert_eq!(type_code("APP_QUERY"), Some(200));
+        let mut msg = Message::create_msg_str_fit("query");
+        msg.set_type(200);
+        assert_eq!(msg.type_display(), "APP_QUERY");
+        msg.set_type(MessageType::FILE_END as u8);
+        assert_eq!(msg.type_display(), "FILE_END");
+    }
+    #[test]
+    fn bad_registrations_refused() {
+        let err = register_type(MessageType::TEXT as u8, "MY_TEXT").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert!(register_type(100, "LATER_LIBRARY_TYPE").is_err());
+        let err = register_type(201, "REPLY").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+        register_type(202, "APP_UPDATE").unwrap();
+        let err = register_type(202, "APP_DELETE").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+        assert!(register_type(203, "APP_UPDATE").is_err());
+    }
+    #[test]
+    fn unknown_codes_displayed_in_hex() {
+        assert_eq!(display_type(5), "UNKNOWN(0x05)");
+        assert_eq!(display_type(0xfe), "UNKNOWN(0xfe)");
+        assert_eq!(type_code("NO_SUCH_TYPE"), None);
+    }
+}
@@ -0,0 +1,124 @@
+/////////////////////////////////////////////////////////////
+// rust_message::status.rs - ERROR reply contents          //
+//                                                         //
+// Jim Fawcett, https://JimFawcett.github.io, 18 Oct 2026  //
+/////////////////////////////////////////////////////////////
+/*
+   ErrorReply is what processing code sends back, as a
+   MessageType::ERROR message, when it can't handle a request.
+
+   ERROR body layout, integers big-endian:
+     status u32 | message length u32 | message utf-8 | details
+   details are whatever bytes remain, often empty.
+
+   ErrorReply implements std::error::Error so it can travel
+   inside a std::io::Error, as Connector::request returns it.
+*/
+
+use crate::*;
+use std::fmt;
+
+/*-- common status codes, any u32 is allowed --*/
+pub const STATUS_BAD_REQUEST:u32 = 400;
+pub const STATUS_NOT_FOUND:u32 = 404;
+pub const STATUS_INTERNAL:u32 = 500;
+pub const STATUS_UNAVAILABLE:u32 = 503;
+
+const STATUS_SIZE:usize = 4;
+const MESSAGE_LEN_SIZE:usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorReply {
+    pub status: u32,
+    pub message: String,
+    pub details: Bytes,
+}
+impl ErrorReply {
+    pub fn new(status: u32, message: &str) -> ErrorReply {
+        ErrorReply { status, message: message.to_string(), details: Bytes::new() }
+    }
+    pub fn with_details(mut self, details: &[u8]) -> ErrorReply {
+        self.details = Bytes::copy_from_slice(details);
+        self
+    }
+    /*-- turns msg into an ERROR reply, keeping its request id --*/
+    pub fn apply_to<M: Msg>(&self, msg: &mut M) {
+        let text = self.message.as_bytes();
+        let mut body = Vec::with_capacity(
+            STATUS_SIZE + MESSAGE_LEN_SIZE + text.len() + self.details.len()
+        );
+        body.extend_from_slice(&self.status.to_be_bytes());
+        body.extend_from_slice(&(text.len() as u32).to_be_bytes());
+        body.extend_from_slice(text);
+        body.extend_from_slice(&self.details);
+        msg.set_type(MessageType::ERROR as u8);
+        msg.set_flags(0);
+        msg.set_body(Bytes::from(body));
+    }
+    pub fn to_msg<M: Msg>(&self) -> M {
+        let mut msg = M::new(HEADER_SIZE);
+        self.apply_to(&mut msg);
+        msg
+    }
+    /*-- None if msg isn't an ERROR or its body is malformed --*/
+    pub fn from_msg<M: Msg>(msg: &M) -> Option<ErrorReply> {
+        if msg.get_type() != MessageType::ERROR as u8 {
+            return None;
+        }
+        let body = msg.get_body();
+        if body.len() < STATUS_SIZE + MESSAGE_LEN_SIZE {
+            return None;
+        }
+        let mut word = [0u8; 4];
+        word.copy_from_slice(&body[..STATUS_SIZE]);
+        let status = u32::from_be_bytes(word);
+        word.copy_from_slice(&body[STATUS_SIZE..STATUS_SIZE + MESSAGE_LEN_SIZE]);
+        let text_len = u32::from_be_bytes(word) as usize;
+        let text_start = STATUS_SIZE + MESSAGE_LEN_SIZE;
+        let text_end = text_start.checked_add(text_len).filter(|&end| end <= body.len())?;
+        let message = std::str::from_utf8(&body[text_start..text_end]).ok()?.to_string();
+        Some(ErrorReply { status, message, details: body.slice(text_end..) })
+    }
+}
+impl fmt::Display for ErrorReply {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "error {}: {}", self.status, self.message)
+    }
+}
+impl std::error::Error for ErrorReply {}
+
+/*-- ErrorReply carried by err, if any --*/
+pub fn error_reply(err: &std::io::Error) -> Option<&ErrorReply> {
+    err.get_ref().and_then(|e| e.downcast_ref::<ErrorReply>())
+}
+pub fn is_error_reply(err: &std::io::Error) -> bool {
+    error_reply(err).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn error_round_trip() {
+        let mut msg = Message::create_msg_str_fit("request");
+        msg.set_request_id(9);
+        ErrorReply::new(STATUS_NOT_FOUND, "no such item")
+            .with_details(b"item 12")
+            .apply_to(&mut msg);
+        assert_eq!(msg.type_display(), "ERROR");
+        assert_eq!(msg.get_request_id(), 9);
+        let reply = msg.get_error().unwrap();
+        assert_eq!(reply.status, STATUS_NOT_FOUND);
+        assert_eq!(reply.message, "no such item");
+        assert_eq!(&reply.details[..], b"item 12");
+        assert_eq!(reply.to_string(), "error 404: no such item");
+    }
+    #[test]
+    fn malformed_or_other_types_rejected() {
+        let msg = Message::create_msg_str_fit("not an error");
+        assert!(msg.get_error().is_none());
+        let mut msg = Message::create_msg_bytes_fit(&[0, 0, 1, 244, 0, 0, 0, 50, b'x']);
+        msg.set_type(MessageType::ERROR as u8);
+        assert!(msg.get_error().is_none());
+    }
+}
@@ -0,0 +1,135 @@
+/////////////////////////////////////////////////////////////
+// rust_message::typed.rs - serde payloads for Message     //
+//                                                         //
+// Jim Fawcett, https://JimFawcett.github.io, 18 Oct 2026  //
+/////////////////////////////////////////////////////////////
+/*
+   Built only with feature "serde".
+
+   Message::from_typed serializes a value into the body and
+   records the encoding in the header flags, so the receiver
+   decodes with msg.decode::<T>() without knowing which
+   encoding the sender chose.
+*/
+
+use crate::*;
+use serde::{Serialize, de::DeserializeOwned};
+use std::fmt;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Encoding {
+    Bincode,
+    Json,
+}
+impl Encoding {
+    pub fn flag(self) -> u8 {
+        match self {
+            Encoding::Bincode => FLAG_BINCODE,
+            Encoding::Json => FLAG_JSON,
+        }
+    }
+    pub fn from_flags(flags: u8) -> Option<Encoding> {
+        match flags & ENCODING_MASK {
+            FLAG_BINCODE => Some(Encoding::Bincode),
+            FLAG_JSON => Some(Encoding::Json),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CodecError {
+    Bincode(bincode::Error),
+    Json(serde_json::Error),
+    /*-- header flags don't name an encoding --*/
+    NotTyped(u8),
+}
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodecError::Bincode(e) => write!(f, "bincode: {}", e),
+            CodecError::Json(e) => write!(f, "json: {}", e),
+            CodecError::NotTyped(flags) => write!(
+                f, "message body has no serde encoding, flags {:#04x}", flags
+            ),
+        }
+    }
+}
+impl std::error::Error for CodecError {}
+
+impl Message {
+    /*-- bincode encoded value, compact and fast --*/
+    pub fn from_typed<T: Serialize>(type_code: u8, value: &T)
+        -> Result<Message, CodecError>
+    {
+        Self::from_typed_with(type_code, value, Encoding::Bincode)
+    }
+    pub fn from_typed_with<T: Serialize>(
+        type_code: u8, value: &T, encoding: Encoding
+    ) -> Result<Message, CodecError> {
+        let body = match encoding {
+            Encoding::Bincode => bincode::serialize(value)
+                .map_err(CodecError::Bincode)?,
+            Encoding::Json => serde_json::to_vec(value)
+                .map_err(CodecError::Json)?,
+        };
+        let mut msg = Message::create_msg_bytes_fit(&body);
+        msg.set_type(type_code);
+        msg.set_flags(encoding.flag());
+        Ok(msg)
+    }
+    /*-- encoding named by header flags, if any --*/
+    pub fn encoding(&self) -> Option<Encoding> {
+        Encoding::from_flags(self.get_flags())
+    }
+    pub fn decode<T: DeserializeOwned>(&self) -> Result<T, CodecError> {
+        let body = self.get_content_bytes();
+        match self.encoding() {
+            Some(Encoding::Bincode) => bincode::deserialize(body)
+                .map_err(CodecError::Bincode),
+            Some(Encoding::Json) => serde_json::from_slice(body)
+                .map_err(CodecError::Json),
+            None => Err(CodecError::NotTyped(self.get_flags())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Point {
+        name: String,
+        x: f64,
+        y: f64,
+    }
+    fn point() -> Point {
+        Point { name: "origin".to_string(), x: 0.0, y: -1.5 }
+    }
+    #[test]
+    fn bincode_round_trip() {
+        let msg = Message::from_typed(MessageType::TEXT as u8, &point()).unwrap();
+        assert_eq!(msg.encoding(), Some(Encoding::Bincode));
+        assert_eq!(msg.get_type(), MessageType::TEXT as u8);
+        assert_eq!(msg.decode::<Point>().unwrap(), point());
+    }
+    #[test]
+    fn json_round_trip() {
+        let msg = Message::from_typed_with(
+            MessageType::TEXT as u8, &point(), Encoding::Json
+        ).unwrap();
+        assert_eq!(msg.encoding(), Some(Encoding::Json));
+        assert!(msg.get_content_str().unwrap().contains("\"origin\""));
+        assert_eq!(msg.decode::<Point>().unwrap(), point());
+    }
+    #[test]
+    fn decode_untyped_fails() {
+        let msg = Message::create_msg_str_fit("plain text");
+        match msg.decode::<Point>() {
+            Err(CodecError::NotTyped(0)) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
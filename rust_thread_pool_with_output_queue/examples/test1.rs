@@ -22,21 +22,15 @@ pub fn test_message_in_pool(
     tp: &BlockingQueue<String>,
     out: &BlockingQueue<String>
 ) {
-    let q = String::from("quit");
     let id = thread::current().id();
-    loop {
+    /*-- de_q returns None when pool is closed and drained --*/
+    while let Some(msg) = tp.de_q() {
         /*-- process message --*/
-        let msg = tp.de_q();
         print!("\n  deQed {:<12} : {:?}", msg, id);
         thread::yield_now();
         /*-- prepare reply --*/
         let reply = format!("reply to {:<12} from {:?}", msg, id);
-        out.en_q(reply);
-        if msg == q {
-            tp.en_q(msg);
-            out.en_q("quit".to_string());
-            break;
-        }
+        let _ = out.en_q(reply);
         thread::yield_now();
     }
 }
@@ -50,18 +44,31 @@ pub fn post_message_to_pool() {
     for i in 0..20 {
         let mut msg = msg.clone();
         msg.push_str(&i.to_string());
-        tp.post(msg);
+        let _ = tp.post(msg);
         // thread::sleep(_millis);
     }
-    tp.post("quit".to_string());
-    loop {
-        let msg = tp.get();
+    tp.close();
+    tp.wait();
+    while let Some(msg) = tp.get() {
         print!("\n  received: {:?}", msg);
-        if msg == "quit".to_string() {
-            break;
+    }
+}
+/*-- submit jobs to pool, collect typed results --*/
+pub fn submit_jobs_to_pool() {
+    let mut tp = ThreadPool::with_threads(4);
+    let handles: Vec<_> = (0..8u64).map(|n| {
+        tp.submit(move || {
+            let id = thread::current().id();
+            (n, n * n, id)
+        })
+    }).collect();
+    for handle in handles {
+        match handle.join() {
+            Ok((n, sq, id)) => print!("\n  {} squared is {:<3} on {:?}", n, sq, id),
+            Err(e) => print!("\n  job failed: {}", e),
         }
     }
-    tp.wait();
+    tp.shutdown();
 }
 // /*-----------------------------------------------------------
 //   Define WorkItem type to execute in ThreadPool<WorkItem>
@@ -128,13 +135,9 @@ fn test0() {
     let handle = thread::spawn(move || {
         print!("\n  child thread started");
         flush();
-        loop {
-            let t = share1.de_q();
+        while let Some(t) = share1.de_q() {
             print!("\n  dequeued {} on child thread", t);
             flush();
-            if &t == "quit" {
-                break;
-            }
         }
         print!("\n  thread shutting down");
         flush();
@@ -142,15 +145,15 @@ fn test0() {
 
     /*-- main thread enqueues messages --*/
     for i in 0..5 {
-        let msg = format!("msg #{}", i.to_string());
+        let msg = format!("msg #{}", i);
         print!("\n  enqueued {:?} on main thread", msg);
         flush();
-        share2.en_q(msg);
+        let _ = share2.en_q(msg);
     }
     /*-- shut down child thread --*/
-    print!("\n  enqueued {:?} on main thread", "quit");
+    print!("\n  closing queue on main thread");
     flush();
-    share2.en_q("quit".to_string());
+    share2.close();
 
     /*-- child thread must complete before exiting --*/
     print!("\n  waiting for child thread to stop");
@@ -167,6 +170,9 @@ fn main() {
 
     //post_workitem_to_pool();
     post_message_to_pool();
+    print!("\n\n  Demonstrate jobs with typed results");
+    print!("\n =====================================");
+    submit_jobs_to_pool();
     // test0();  // test BlockingQueue
 
     print!("\n\n  That's all Folks!\n");
@@ -0,0 +1,113 @@
+/////////////////////////////////////////////////////////////
+// rust_thread_pool::job.rs - work items and their results //
+//                                                         //
+// Jim Fawcett, https://JimFawcett.github.io, 18 Oct 2026  //
+/////////////////////////////////////////////////////////////
+/*
+   ThreadPool::submit wraps a closure as a Job, a boxed
+   FnOnce the pool's threads run, and returns a JobHandle<R>
+   for the closure's result.
+
+   The result travels through a BlockingQueue holding one
+   element, closed when the job finishes or is dropped
+   without running, so a handle never waits forever.
+   Panics are caught, the thread lives on, and the handle
+   reports JobError::Panicked.
+*/
+
+use rust_blocking_queue::BlockingQueue;
+use std::any::Any;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::time::Duration;
+
+/*-- work item, run by calling it --*/
+pub type Job = Box<dyn FnOnce() + Send + 'static>;
+
+#[derive(Debug)]
+pub enum JobError {
+    /// job panicked, holds the panic payload
+    Panicked(Box<dyn Any + Send + 'static>),
+    /// pool shut down before the job ran
+    Cancelled,
+}
+impl fmt::Display for JobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobError::Panicked(_) => write!(f, "job panicked"),
+            JobError::Cancelled => write!(f, "job cancelled before it ran"),
+        }
+    }
+}
+impl std::error::Error for JobError {}
+
+pub type JobResult<R> = Result<R, JobError>;
+
+/*-- closes the result queue however the job ends --*/
+struct Sender<R>(Arc<BlockingQueue<JobResult<R>>>);
+impl<R> Drop for Sender<R> {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+/*-- job for the pool and the handle for its result --*/
+pub(crate) fn job<F, R>(f: F) -> (Job, JobHandle<R>)
+where F: FnOnce() -> R + Send + 'static, R: Send + 'static
+{
+    let result = Arc::new(BlockingQueue::with_capacity(1));
+    let sender = Sender(Arc::clone(&result));
+    let job: Job = Box::new(move || {
+        let outcome = panic::catch_unwind(AssertUnwindSafe(f)).map_err(JobError::Panicked);
+        let _ = sender.0.en_q(outcome);
+    });
+    (job, JobHandle { result, taken: false })
+}
+
+#[derive(Debug)]
+/// Result of a job submitted to a ThreadPool
+pub struct JobHandle<R> {
+    result: Arc<BlockingQueue<JobResult<R>>>,
+    taken: bool,
+}
+impl<R> JobHandle<R> {
+    /*-- wait for the job to finish, panics if result was taken --*/
+    pub fn join(self) -> JobResult<R> {
+        if self.taken {
+            panic!("JobHandle::join after its result was taken");
+        }
+        self.result.de_q().unwrap_or(Err(JobError::Cancelled))
+    }
+    /*-- result if the job has finished, None while it runs --*/
+    /*-- None again once the result has been taken --*/
+    pub fn try_result(&mut self) -> Option<JobResult<R>> {
+        let outcome = self.result.try_de_q();
+        self.take(outcome)
+    }
+    /*-- wait at most timeout, None if the job is still running --*/
+    pub fn join_timeout(&mut self, timeout: Duration) -> Option<JobResult<R>> {
+        let outcome = self.result.de_q_timeout(timeout);
+        self.take(outcome)
+    }
+    /*-- true once the job has finished, or won't run --*/
+    pub fn is_finished(&self) -> bool {
+        self.result.is_closed()
+    }
+    fn take(&mut self, outcome: Option<JobResult<R>>) -> Option<JobResult<R>> {
+        if self.taken {
+            return None;
+        }
+        match outcome {
+            Some(outcome) => {
+                self.taken = true;
+                Some(outcome)
+            }
+            None if self.result.is_closed() && self.result.is_empty() => {
+                self.taken = true;
+                Some(Err(JobError::Cancelled))
+            }
+            None => None,
+        }
+    }
+}
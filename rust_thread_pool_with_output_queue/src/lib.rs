@@ -4,27 +4,58 @@
 // Jim Fawcett, https://JimFawcett.github.com, 29 Jun 2020 //
 /////////////////////////////////////////////////////////////
 /*
-   There are two undefined methods for ThreadPool<M>
-   that need to be implemented before this design is
-   complete, e.g.:
-   - post_work_item posts a function object to input queue
-   - get_message retrieves results from an output queue
+   ThreadPool threads run jobs, closures queued by submit,
+   see job.rs.  submit returns a JobHandle<R> for the job's
+   result.  ThreadPool::with_threads(n) makes a pool for jobs
+   only.
+
+   ThreadPool::<M>::new(n, f) also runs f on each thread, as
+   a job, with input and output message queues, served by
+   post and get.  Those threads take other jobs after f
+   returns.
+
+   Closing the pool closes its input queue.  Thread procs
+   see de_q return None once the queue is drained, and
+   return.  wait closes the output queue after the threads
+   finish, so get returns None when every result is taken.
+
+   shutdown closes input, finishes queued work, and joins
+   the threads, as dropping the pool does.  shutdown_now
+   returns queued work instead of running it.
 */
 #![allow(dead_code)]
 use std::fmt::*;
 use rust_blocking_queue::*;
 use std::thread::*;
 use std::sync::*;
-use std::default::{Default};
 
-#[derive(Debug)]
-pub struct ThreadPool<M> 
+mod job;
+pub use job::{Job, JobError, JobHandle, JobResult};
+
+pub struct ThreadPool<M = ()> 
 {
     sibq: Arc<BlockingQueue<M>>,
     sobq: Arc<BlockingQueue<M>>,
+    jobs: Arc<BlockingQueue<Job>>,
     thrd: Vec<Option<JoinHandle<()>>>
     /* see note below about Option */
 }
+impl<M> Debug for ThreadPool<M> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ThreadPool")
+            .field("threads", &self.thrd.len())
+            .field("queued_jobs", &self.jobs.len())
+            .field("queued_input", &self.sibq.len())
+            .field("queued_output", &self.sobq.len())
+            .finish()
+    }
+}
+impl ThreadPool {
+    /*-- construct threadpool for jobs only, starting nt threads --*/
+    pub fn with_threads(nt:u8) -> ThreadPool {
+        ThreadPool::start(nt)
+    }
+}
 impl<M> ThreadPool<M> 
 where M: Send + 'static
 {
@@ -33,36 +64,84 @@ where M: Send + 'static
       provide threadpool processing as f:F in new 
     */
     pub fn new<F>(nt:u8, f:F) -> ThreadPool<M> 
-    where F: FnOnce(&BlockingQueue<M>, &BlockingQueue<M>) -> () + Send + 'static + Copy
+    where F: FnOnce(&BlockingQueue<M>, &BlockingQueue<M>) + Send + 'static + Copy
     {
+        let tp = ThreadPool::start(nt);
+        for _i in 0..nt {
+            /*----------------------------------------------- 
+              refs to shared queues are captured by job
+              closure, one job per thread
+            */
+            let siq = Arc::clone(&tp.sibq);
+            let soq = Arc::clone(&tp.sobq);
+            let _ = tp.jobs.en_q(Box::new(move || { 
+                f(&siq, &soq);  // thread_pool_processing
+            }));
+        }
+        tp
+    }
+    /*-- start nt threads, each running queued jobs --*/
+    fn start(nt:u8) -> ThreadPool<M> {
         /* safely share BlockingQueue with Arc */
         let siqm = Arc::new(BlockingQueue::<M>::new());
         let soqm = Arc::new(BlockingQueue::<M>::new());
+        let jobs = Arc::new(BlockingQueue::<Job>::new());
         let mut vt = Vec::<Option<JoinHandle<()>>>::new();
-        /* start nt threads */
         for _i in 0..nt {
-            /*----------------------------------------------- 
-              ref sq to master shared queue (siqm) is captured
-              by thread proc closure 
-            */
-            let siq = Arc::clone(&siqm);
-            let soq = Arc::clone(&soqm);
+            let jq = Arc::clone(&jobs);
             let handle = std::thread::spawn( move || { 
-                f(&siq, &soq);  // thread_pool_processing
+                while let Some(job) = jq.de_q() {
+                    job();
+                }
             });
             vt.push(Some(handle));
         }
         Self { // return newly created threadpool
             sibq: siqm,
             sobq: soqm,
+            jobs,
             thrd: vt, 
         }
     }
-    /*-- wait for threads to finish --*/
+    /*-----------------------------------------------------
+      queue f to run on a pool thread, JobHandle returns
+      its result, Cancelled if the pool has shut down
+    */
+    pub fn submit<F, R>(&self, f:F) -> JobHandle<R>
+    where F: FnOnce() -> R + Send + 'static, R: Send + 'static
+    {
+        let (job, handle) = job::job(f);
+        /* a refused job is dropped, so its handle reports Cancelled */
+        let _ = self.jobs.en_q(job);
+        handle
+    }
+    /*-- post to ThreadPool queue, returns msg if closed --*/
+    pub fn post(&mut self, _msg:M) -> std::result::Result<(), M>
+    where M:Debug {
+        self.sibq.en_q(_msg)
+    }
+    /*-- return results to caller, None after wait drains --*/
+    pub fn get(&mut self) -> Option<M> 
+    where M:Debug {
+        self.sobq.de_q()
+    }
+}
+impl<M> ThreadPool<M> {
+    /*-- stop accepting posts, threads finish queued work --*/
+    pub fn close(&self) {
+        self.sibq.close();
+    }
+    /*-----------------------------------------------------
+      wait for threads to finish queued jobs, call close
+      first if threads run f
+    */
     pub fn wait(&mut self) {
+        self.jobs.close();
         
         for handle in &mut self.thrd {
-            let _ = handle.take().unwrap().join();
+            if let Some(handle) = handle.take() {
+                let _ = handle.join();
+            }
             /*
               This is a hack!
               Without the Option, wrapping threadhandle, can't move threadhandle
@@ -75,21 +154,51 @@ where M: Send + 'static
               https://users.rust-lang.org/t/spawn-threads-and-join-in-destructor/1613
             */
         }
+        self.sobq.close();
     }
-    /*-- post to ThreadPool queue --*/
-    pub fn post(&mut self, _msg:M) 
-    where M:Debug {
-        self.sibq.en_q(_msg);
+    /*-- finish queued jobs and messages, then join threads --*/
+    pub fn shutdown(&mut self) {
+        self.close();
+        self.wait();
     }
-    /*-- return results to caller --*/
-    pub fn get(&mut self) -> M 
-    where M:Debug + Default {
-        let m:M = self.sobq.de_q();
-        m
+    /*-----------------------------------------------------
+      stop taking queued work, join threads once running
+      jobs finish, and return what never ran.  Dropping
+      the returned jobs cancels their JobHandles.
+    */
+    pub fn shutdown_now(&mut self) -> Pending<M> {
+        self.jobs.close();
+        self.sibq.close();
+        let pending = Pending {
+            jobs: self.jobs.drain(),
+            messages: self.sibq.drain(),
+        };
+        self.wait();
+        pending
+    }
+    /*-- true once every thread has exited --*/
+    pub fn done(&self) -> bool {
+        self.thrd.iter().all(|h| h.as_ref().is_none_or(JoinHandle::is_finished))
+    }
+}
+/*-- graceful shutdown, queued work finishes first --*/
+impl<M> Drop for ThreadPool<M> {
+    fn drop(&mut self) {
+        self.shutdown();
     }
-    /*-- return results to caller --*/
-    pub fn done(&mut self) -> bool {
-        false
+}
+
+/*-- work shutdown_now took from the pool's queues --*/
+pub struct Pending<M> {
+    pub jobs: Vec<Job>,
+    pub messages: Vec<M>,
+}
+impl<M: Debug> Debug for Pending<M> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Pending")
+            .field("jobs", &self.jobs.len())
+            .field("messages", &self.messages)
+            .finish()
     }
 }
 
@@ -98,14 +207,134 @@ mod tests {
     use super::*;
     #[test]
     fn test_new() {
-        let test = |bq:&BlockingQueue<String>| { 
-            let msg = bq.de_q();
-            print!("\n  {:?}", msg);
+        let test = |bq:&BlockingQueue<String>, out:&BlockingQueue<String>| { 
+            while let Some(msg) = bq.de_q() {
+                let _ = out.en_q(format!("reply to {}", msg));
+            }
         };
         let mut tp = ThreadPool::<String>::new(2, test);
         let msg = "test message".to_string();
-        tp.post(msg);
-        tp.post("quit".to_string());
+        tp.post(msg).unwrap();
+        tp.close();
+        assert!(tp.post("too late".to_string()).is_err());
+        tp.wait();
+        assert_eq!(tp.get().as_deref(), Some("reply to test message"));
+        assert_eq!(tp.get(), None);
+    }
+    #[test]
+    fn submit_returns_typed_results() {
+        let mut tp = ThreadPool::with_threads(3);
+        let handles: Vec<JobHandle<u64>> = (1..=10u64)
+            .map(|n| tp.submit(move || (1..=n).product()))
+            .collect();
+        let name = tp.submit(|| "done".to_string());
+        let results: Vec<u64> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(results[4], 120);
+        assert_eq!(results[9], 3_628_800);
+        assert_eq!(name.join().unwrap(), "done");
+        tp.wait();
+    }
+    #[test]
+    fn panicking_job_reported_and_thread_survives() {
+        let mut tp = ThreadPool::with_threads(1);
+        let bad = tp.submit(|| -> i32 { panic!("job failed") });
+        let good = tp.submit(|| 7);
+        match bad.join() {
+            Err(JobError::Panicked(payload)) => {
+                assert_eq!(payload.downcast_ref::<&str>(), Some(&"job failed"));
+            }
+            other => panic!("expected panic, got {:?}", other),
+        }
+        assert_eq!(good.join().unwrap(), 7);
         tp.wait();
     }
+    #[test]
+    fn try_result_and_join_timeout() {
+        let mut tp = ThreadPool::with_threads(1);
+        let (tx, rx) = mpsc::channel::<()>();
+        let mut h = tp.submit(move || { rx.recv().unwrap(); 42 });
+        assert!(h.try_result().is_none());
+        assert!(h.join_timeout(std::time::Duration::from_millis(20)).is_none());
+        assert!(!h.is_finished());
+        tx.send(()).unwrap();
+        let r = h.join_timeout(std::time::Duration::from_secs(10));
+        assert_eq!(r.unwrap().unwrap(), 42);
+        assert!(h.try_result().is_none());
+        tp.wait();
+        assert!(matches!(tp.submit(|| 1).join(), Err(JobError::Cancelled)));
+    }
+    #[test]
+    fn shutdown_finishes_queued_jobs_and_repeats_safely() {
+        let mut tp = ThreadPool::with_threads(2);
+        let count = Arc::new(atomic::AtomicUsize::new(0));
+        for _ in 0..20 {
+            let count = Arc::clone(&count);
+            tp.submit(move || count.fetch_add(1, atomic::Ordering::SeqCst));
+        }
+        tp.shutdown();
+        assert!(tp.done());
+        assert_eq!(count.load(atomic::Ordering::SeqCst), 20);
+        tp.shutdown();
+        tp.wait();
+    }
+    #[test]
+    fn shutdown_now_returns_pending_work() {
+        let mut tp = ThreadPool::with_threads(1);
+        let (tx, rx) = mpsc::channel::<()>();
+        let running = tp.submit(move || rx.recv().is_ok());
+        let queued: Vec<JobHandle<usize>> = (0..3).map(|n| tp.submit(move || n)).collect();
+        while tp.jobs.len() != 3 {
+            std::thread::yield_now();
+        }
+        let releaser = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            tx.send(()).unwrap();
+        });
+        let pending = tp.shutdown_now();
+        assert_eq!(pending.jobs.len(), 3);
+        assert!(tp.done());
+        assert!(running.join().unwrap());
+        let mut first = pending.jobs.into_iter();
+        (first.next().unwrap())();
+        drop(first);
+        let results: Vec<_> = queued.into_iter().map(|h| h.join().ok()).collect();
+        assert_eq!(results, [Some(0), None, None]);
+        releaser.join().unwrap();
+    }
+    #[test]
+    fn shutdown_now_returns_unprocessed_messages() {
+        let mut tp = ThreadPool::<String>::new(1, |bq: &BlockingQueue<String>, out: &BlockingQueue<String>| {
+            while let Some(msg) = bq.de_q() {
+                let _ = out.en_q(msg);
+            }
+        });
+        /* f is itself a queued job until a thread takes it */
+        while !tp.jobs.is_empty() {
+            std::thread::yield_now();
+        }
+        tp.sibq.en_q_all(vec!["a".to_string(), "b".to_string()]).unwrap();
+        let pending = tp.shutdown_now();
+        assert!(pending.jobs.is_empty());
+        let mut seen: Vec<String> = pending.messages;
+        while let Some(msg) = tp.get() {
+            seen.push(msg);
+        }
+        seen.sort();
+        assert_eq!(seen, ["a", "b"]);
+    }
+    #[test]
+    fn drop_joins_after_queued_jobs() {
+        let count = Arc::new(atomic::AtomicUsize::new(0));
+        {
+            let tp = ThreadPool::with_threads(2);
+            for _ in 0..10 {
+                let count = Arc::clone(&count);
+                tp.submit(move || {
+                    std::thread::sleep(std::time::Duration::from_millis(2));
+                    count.fetch_add(1, atomic::Ordering::SeqCst);
+                });
+            }
+        }
+        assert_eq!(count.load(atomic::Ordering::SeqCst), 10);
+    }
 }
@@ -6,16 +6,27 @@
 /*
    Defines traits used for rust_comm:
    - Logger
+   - Clock
    - MsgType
    - Msg
    - Sndr<M>
    - Rcvr<M>
    - Process<M>
+
+   and CancelToken, shared by whoever cancels a request and
+   the code processing it.
+
+   Message bodies are bytes::Bytes, re-exported here, so
+   clones share one reference-counted buffer.
 */
 
 use std::net::{TcpStream};
 use std::io::{BufReader, BufWriter, Result};
 use std::str::Utf8Error;
+use std::convert::TryFrom;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+pub use bytes::Bytes;
 // use rust_blocking_queue::*;
 
 // pub const MSG_SIZE:usize = 4096;
@@ -24,6 +35,28 @@ pub trait Logger : Send {
     fn write(msg: &str);
 }
 
+/*-- milliseconds since the unix epoch, replaceable in tests --*/
+pub trait Clock : Send + Sync + std::fmt::Debug {
+    fn now_millis(&self) -> u64;
+}
+
+/*-- clones share one flag, once cancelled stays cancelled --*/
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
 pub trait MsgType : Send + std::fmt::Debug {
     fn get_type(&self) -> u8;
     fn set_type(&mut self, mt:u8);
@@ -36,6 +69,32 @@ pub trait Msg : Send + std::fmt::Debug {
     fn is_empty(&self) -> bool;
     fn set_type(&mut self, mt:u8);
     fn get_type(&self) -> u8;
+    fn set_flags(&mut self, flags:u8);
+    fn get_flags(&self) -> u8;
+    fn set_priority(&mut self, priority:u8);
+    fn get_priority(&self) -> u8;
+    /*-- deadline in ms since the unix epoch, 0 means none --*/
+    fn set_deadline(&mut self, deadline_ms:u64);
+    fn get_deadline(&self) -> u64;
+    fn set_ttl(&mut self, ttl: std::time::Duration, clock: &dyn Clock) {
+        let ttl_ms = u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX);
+        self.set_deadline(clock.now_millis().saturating_add(ttl_ms));
+    }
+    fn is_expired(&self, now_ms: u64) -> bool {
+        let deadline = self.get_deadline();
+        deadline != 0 && now_ms >= deadline
+    }
+    /*-- correlates requests, replies, and cancels, 0 means none --*/
+    fn set_request_id(&mut self, id:u64);
+    fn get_request_id(&self) -> u64;
+    /*-- key/value headers, setting a key replaces its value --*/
+    /*-- InvalidInput if headers would exceed MAX_META_SIZE --*/
+    fn set_header(&mut self, key:&str, value:&str) -> Result<()>;
+    fn get_header(&self, key:&str) -> Option<&str>;
+    fn remove_header(&mut self, key:&str) -> bool;
+    /*-- encoded key/value section, shared, for framing --*/
+    fn set_meta(&mut self, meta: Bytes);
+    fn get_meta(&self) -> Bytes;
     fn set_content_bytes(&mut self, buff: &[u8]);
     fn get_content_bytes(&self) -> &[u8];
     fn set_content_str(&mut self, s: &str);
@@ -43,11 +102,11 @@ pub trait Msg : Send + std::fmt::Debug {
     fn show_message(&self, fold:usize);
     fn set_content_size(&mut self, sz:usize);
     fn get_content_size(&self) -> usize;
-    fn set_bytes(&mut self, buff:&[u8]);
-    fn get_bytes(&self) -> &[u8];
-    fn get_mut_bytes(&mut self) -> &mut [u8];
-    fn get_ref(&self) -> &Vec<u8>;
-    fn get_mut_ref(&mut self) -> &mut Vec<u8>;
+    fn get_header_bytes(&self) -> &[u8];
+    /*-- takes ownership of body without copying, sets content size --*/
+    fn set_body(&mut self, body: Bytes);
+    /*-- shares content with self, no copy --*/
+    fn get_body(&self) -> Bytes;
     fn type_display(&self) -> String;
 }
 pub trait Sndr<M> : Send 
@@ -55,22 +114,49 @@ where M: Msg + Clone + Send + Default,
 {
     fn send_message(msg: &M, stream: &mut TcpStream) -> Result<()>;
     fn buf_send_message(msg: &M, stream: &mut BufWriter<TcpStream>) -> Result<()>;
+    /*-- writes msgs in order, caller decides when to flush --*/
+    fn buf_send_batch(msgs: &[M], stream: &mut BufWriter<TcpStream>) -> Result<()> {
+        for msg in msgs {
+            Self::buf_send_message(msg, stream)?;
+        }
+        Ok(())
+    }
 }
 pub trait Rcvr<M>: Send 
 where M: Msg + Clone + Send + Default,
 {
     fn recv_message(stream: &mut TcpStream) -> Result<M>;
     fn buf_recv_message(stream: &mut BufReader<TcpStream>) -> Result<M>;
+    /*-- as above, refusing frames over max_frame bytes, as agreed in Hello --*/
+    fn recv_message_limited(stream: &mut TcpStream, _max_frame: u64) -> Result<M> {
+        Self::recv_message(stream)
+    }
+    fn buf_recv_message_limited(stream: &mut BufReader<TcpStream>, _max_frame: u64) -> Result<M> {
+        Self::buf_recv_message(stream)
+    }
 }
 pub trait Process<M> : Send 
 where M: Msg + Clone + Send + Default,
 {
     fn process_message(m: &mut M);
+    /*-- long running processing should poll token and stop early --*/
+    fn process_cancellable(m: &mut M, _token: &CancelToken) {
+        Self::process_message(m);
+    }
 }
 #[cfg(test)]
 mod tests {
+    use super::*;
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+    #[test]
+    fn cancel_token_clones_share_state() {
+        let token = CancelToken::new();
+        let other = token.clone();
+        assert!(!other.is_cancelled());
+        token.cancel();
+        assert!(other.is_cancelled());
+    }
 }